@@ -0,0 +1,42 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::io::Read;
+
+use data_onion::ascii85::{decode_ascii85_fast, decode_ascii85_str, Ascii85Reader};
+
+const LAYER: &str = include_str!("../layers/00.txt");
+
+fn payload() -> &'static str {
+    &LAYER[LAYER.find("<~").expect("no payload in layer file")..]
+}
+
+fn bench_decoders(c: &mut Criterion) {
+    let payload = payload();
+    assert_eq!(
+        decode_ascii85_fast(payload).unwrap(),
+        decode_ascii85_str(payload).unwrap()
+    );
+
+    let mut group = c.benchmark_group("layer 0");
+    group.throughput(criterion::Throughput::Bytes(payload.len() as u64));
+
+    group.bench_function("parser", |b| {
+        b.iter(|| decode_ascii85_str(black_box(payload)).unwrap())
+    });
+    group.bench_function("fast", |b| {
+        b.iter(|| decode_ascii85_fast(black_box(payload)).unwrap())
+    });
+    group.bench_function("reader", |b| {
+        b.iter(|| {
+            let mut out = vec![];
+            Ascii85Reader::new(black_box(payload.as_bytes()))
+                .read_to_end(&mut out)
+                .unwrap();
+            out
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_decoders);
+criterion_main!(benches);
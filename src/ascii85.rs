@@ -1,77 +1,199 @@
 use nom::branch::alt;
 use nom::bytes::complete::tag;
 use nom::character::complete::{multispace0, satisfy};
-use nom::combinator::{all_consuming, map, map_res};
+use nom::combinator::{all_consuming, map};
+use nom::error::ErrorKind;
 use nom::multi::{many0, many_m_n};
 use nom::sequence::{delimited, preceded, terminated};
 use nom::Finish;
 use nom::IResult;
-use std::error::Error;
-use std::fmt;
-use std::fmt::{Display, Formatter};
 
+pub use btoa::{decode_btoa, decode_btoa_with_options, encode_btoa};
+pub use buffer::{
+    decode_ascii85_fast, decode_ascii85_fast_with_options, decode_into, decode_into_with_options,
+    decoded_len,
+};
+pub use error::{DecodeError, DecodeErrorKind, Position};
+pub use reader::Ascii85Reader;
+pub use recover::{decode_ascii85_recover, Diagnostic, Recovered};
+pub use scan::{decode_ascii85_blocks, Ascii85Block};
+pub use variant::{decode_base85, encode_base85, Variant};
+pub use writer::{Ascii85Writer, EncodeOptions};
+pub use z85::{decode_z85, encode_z85};
+
+mod btoa;
+mod buffer;
+mod error;
+mod reader;
+mod recover;
+mod scan;
+mod variant;
+mod writer;
+mod z85;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Accept final groups of a single character, which decode to nothing,
+    /// and `z` right after a partial group.
+    #[default]
+    Lenient,
+    /// Follow the Adobe specification to the letter.
+    Strict,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecodeOptions {
+    pub mode: Mode,
+}
+
+/// Parser error, keeping track of the remaining input to locate it.
 #[derive(Debug)]
-pub struct DecodeError {
-    error: String,
+struct ParseError<'a> {
+    input: &'a str,
+    kind: DecodeErrorKind,
 }
 
-impl Display for DecodeError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.error)
+impl<'a> ParseError<'a> {
+    /// Error for an unexpected character, or `eof` if the input is exhausted.
+    fn unexpected(input: &'a str, eof: DecodeErrorKind) -> Self {
+        let kind = input
+            .chars()
+            .next()
+            .map_or(eof, DecodeErrorKind::InvalidCharacter);
+        Self { input, kind }
     }
 }
 
-impl Error for DecodeError {}
+impl<'a> nom::error::ParseError<&'a str> for ParseError<'a> {
+    fn from_error_kind(input: &'a str, _kind: ErrorKind) -> Self {
+        Self::unexpected(input, DecodeErrorKind::MissingEndTag)
+    }
+
+    fn append(_input: &'a str, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+type ParseResult<'a, T> = IResult<&'a str, T, ParseError<'a>>;
 
 fn is_ascii85_value(c: char) -> bool {
     ('!'..='u').contains(&c)
 }
 
-fn ws(i: &str) -> IResult<&str, &str> {
+fn ws(i: &str) -> ParseResult<'_, &str> {
     multispace0(i)
 }
 
-fn chr(i: &str) -> IResult<&str, char> {
+fn chr(i: &str) -> ParseResult<'_, char> {
     preceded(ws, satisfy(is_ascii85_value))(i)
 }
 
-fn z(i: &str) -> IResult<&str, Vec<u8>> {
+/// Flavour of the payload body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dialect {
+    Adobe,
+    /// Also accepts `y` for four spaces.
+    Btoa,
+}
+
+fn z(i: &str) -> ParseResult<'_, Vec<u8>> {
     map(preceded(ws, tag("z")), |_| vec![0, 0, 0, 0])(i)
 }
 
-fn fives(i: &str) -> IResult<&str, Vec<u8>> {
-    map_res(many_m_n(1, 5, chr), |v| decode_sequence(&v))(i)
+fn y(i: &str) -> ParseResult<'_, Vec<u8>> {
+    map(preceded(ws, tag("y")), |_| vec![b' '; 4])(i)
+}
+
+fn fives<'a>(
+    options: DecodeOptions,
+    dialect: Dialect,
+) -> impl FnMut(&'a str) -> ParseResult<'a, Vec<u8>> {
+    move |i| {
+        let (i, _) = ws(i)?;
+        let (rest, v) = many_m_n(1, 5, chr)(i)?;
+
+        if options.mode == Mode::Strict && v.len() < 5 {
+            let (next, _) = ws(rest)?;
+            if next.starts_with('z') || (dialect == Dialect::Btoa && next.starts_with('y')) {
+                return Err(nom::Err::Failure(ParseError {
+                    input: next,
+                    kind: DecodeErrorKind::ZInsideGroup,
+                }));
+            }
+            if v.len() == 1 && next.starts_with("~>") {
+                return Err(nom::Err::Failure(ParseError {
+                    input: i,
+                    kind: DecodeErrorKind::TruncatedGroup,
+                }));
+            }
+        }
+
+        // a valid group that cannot be decoded is fatal
+        decode_sequence(&v)
+            .map(|b| (rest, b))
+            .map_err(|kind| nom::Err::Failure(ParseError { input: i, kind }))
+    }
+}
+
+fn sequence<'a>(
+    options: DecodeOptions,
+    dialect: Dialect,
+) -> impl FnMut(&'a str) -> ParseResult<'a, Vec<u8>> {
+    move |i| match dialect {
+        Dialect::Adobe => alt((z, fives(options, dialect)))(i),
+        Dialect::Btoa => alt((z, y, fives(options, dialect)))(i),
+    }
 }
 
-fn sequence(i: &str) -> IResult<&str, Vec<u8>> {
-    alt((z, fives))(i)
+fn sequences<'a>(
+    options: DecodeOptions,
+    dialect: Dialect,
+) -> impl FnMut(&'a str) -> ParseResult<'a, Vec<u8>> {
+    map(many0(sequence(options, dialect)), |seqs| {
+        seqs.into_iter().flatten().collect()
+    })
 }
 
-fn sequences(i: &str) -> IResult<&str, Vec<u8>> {
-    map(many0(sequence), |seqs| seqs.into_iter().flatten().collect())(i)
+fn start_tag(i: &str) -> ParseResult<'_, &str> {
+    let (i, _) = ws(i)?;
+    tag("<~")(i).map_err(|e: nom::Err<ParseError>| {
+        e.map(|_| ParseError {
+            input: i,
+            kind: DecodeErrorKind::MissingStartTag,
+        })
+    })
 }
 
-fn payload(i: &str) -> IResult<&str, Vec<u8>> {
-    let start_tag = preceded(ws, tag("<~"));
-    let end_tag = preceded(ws, tag("~>"));
+fn end_tag(i: &str) -> ParseResult<'_, &str> {
+    let (i, _) = ws(i)?;
+    tag("~>")(i).map_err(|e: nom::Err<ParseError>| {
+        e.map(|_| ParseError::unexpected(i, DecodeErrorKind::MissingEndTag))
+    })
+}
 
-    delimited(start_tag, sequences, end_tag)(i)
+fn payload<'a>(options: DecodeOptions) -> impl FnMut(&'a str) -> ParseResult<'a, Vec<u8>> {
+    delimited(start_tag, sequences(options, Dialect::Adobe), end_tag)
 }
 
-fn parse_ascii85(i: &str) -> IResult<&str, Vec<u8>> {
-    all_consuming(terminated(payload, ws))(i)
+fn parse_ascii85(i: &str, options: DecodeOptions) -> ParseResult<'_, Vec<u8>> {
+    all_consuming(terminated(payload(options), ws))(i)
 }
 
 pub fn decode_ascii85_str(b: &str) -> Result<Vec<u8>, DecodeError> {
-    parse_ascii85(b)
+    decode_ascii85_with_options(b, DecodeOptions::default())
+}
+
+pub fn decode_ascii85_with_options(
+    b: &str,
+    options: DecodeOptions,
+) -> Result<Vec<u8>, DecodeError> {
+    parse_ascii85(b, options)
         .finish()
-        .map_err(|e| DecodeError {
-            error: e.to_string(),
-        })
+        .map_err(|e| DecodeError::at(e.kind, b, b.len() - e.input.len()))
         .map(|(_, v)| v)
 }
 
-fn decode_sequence(b: &[char]) -> Result<Vec<u8>, DecodeError> {
+fn decode_sequence(b: &[char]) -> Result<Vec<u8>, DecodeErrorKind> {
     debug_assert!(!b.is_empty());
 
     let vals = (0..5)
@@ -80,17 +202,11 @@ fn decode_sequence(b: &[char]) -> Result<Vec<u8>, DecodeError> {
             (*c as u8)
                 .checked_sub(33)
                 .map(|n| n as u32)
-                .ok_or(DecodeError {
-                    error: format!("Invalid character '{}'", *c),
-                })
+                .ok_or(DecodeErrorKind::InvalidCharacter(*c))
         })
         .collect::<Result<Vec<_>, _>>()?;
 
-    // compose 32-bit value
-    let n: u32 = vals[0] * 52200625 + vals[1] * 614125 + vals[2] * 7225 + vals[3] * 85 + vals[4];
-
-    // create 4 bytes out of this
-    let bytes = n.to_be_bytes();
+    let bytes = decode_group(&vals)?;
 
     // truncate output if necessary
     let bytes = &bytes[0..(b.len() - 1)];
@@ -98,6 +214,111 @@ fn decode_sequence(b: &[char]) -> Result<Vec<u8>, DecodeError> {
     Ok(Vec::from(bytes))
 }
 
+/// Compose a group of 5 base 85 digits into 4 bytes.
+///
+/// Groups above `s8W-!`, i.e. `u32::MAX`, are rejected.
+fn decode_group(vals: &[u32]) -> Result<[u8; 4], DecodeErrorKind> {
+    debug_assert!(vals.len() == 5);
+
+    // compose 32-bit value
+    let n = vals
+        .iter()
+        .try_fold(0u32, |n, &v| n.checked_mul(85)?.checked_add(v))
+        .ok_or(DecodeErrorKind::GroupOverflow)?;
+
+    // create 4 bytes out of this
+    Ok(n.to_be_bytes())
+}
+
+/// Decode text using an arbitrary base 85 alphabet, where digit values are
+/// character positions.
+/// A short final group is padded with the highest digit, then truncated.
+fn decode_alphabet(s: &str, alphabet: &[u8; 85]) -> Result<Vec<u8>, DecodeError> {
+    let chars = s.char_indices().collect::<Vec<_>>();
+    let mut result = Vec::with_capacity(chars.len() / 5 * 4 + 4);
+
+    for group in chars.chunks(5) {
+        let (start, _) = group[0];
+        let vals = (0..5)
+            .map(|n| match group.get(n) {
+                Some(&(i, c)) => alphabet
+                    .iter()
+                    .position(|&a| a as char == c)
+                    .map(|v| v as u32)
+                    .ok_or_else(|| DecodeError::at(DecodeErrorKind::InvalidCharacter(c), s, i)),
+                None => Ok(84),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let bytes = decode_group(&vals).map_err(|kind| DecodeError::at(kind, s, start))?;
+        result.extend_from_slice(&bytes[0..(group.len() - 1)]);
+    }
+
+    Ok(result)
+}
+
+/// Encode a buffer using an arbitrary base 85 alphabet.
+/// A short final group is padded with zeroes, then truncated.
+fn encode_alphabet(b: &[u8], alphabet: &[u8; 85]) -> String {
+    b.chunks(4)
+        .flat_map(|chunk| {
+            let digits = encode_group(chunk);
+            digits
+                .into_iter()
+                .take(chunk.len() + 1)
+                .map(|d| alphabet[d as usize] as char)
+        })
+        .collect()
+}
+
+/// Decompose 4 bytes, padded with zeroes if necessary, into 5 base 85 digits.
+fn encode_group(b: &[u8]) -> [u8; 5] {
+    debug_assert!(!b.is_empty() && b.len() <= 4);
+
+    // pad input with zeroes
+    let mut bytes = [0u8; 4];
+    bytes[..b.len()].copy_from_slice(b);
+    let mut n = u32::from_be_bytes(bytes);
+
+    // decompose 32-bit value into base 85 digits
+    let mut digits = [0u8; 5];
+    for d in digits.iter_mut().rev() {
+        *d = (n % 85) as u8;
+        n /= 85;
+    }
+
+    digits
+}
+
+pub fn encode_ascii85(b: &[u8]) -> String {
+    let mut s = String::with_capacity(4 + b.len().div_ceil(4) * 5);
+
+    s.push_str("<~");
+    for chunk in b.chunks(4) {
+        s.extend(encode_sequence(chunk));
+    }
+    s.push_str("~>");
+
+    s
+}
+
+fn encode_sequence(b: &[u8]) -> Vec<char> {
+    debug_assert!(!b.is_empty() && b.len() <= 4);
+
+    // only complete groups may use the shortcut
+    if b == [0, 0, 0, 0] {
+        return vec!['z'];
+    }
+
+    let digits = encode_group(b);
+
+    // truncate output if necessary
+    digits[0..(b.len() + 1)]
+        .iter()
+        .map(|&d| (d + 33) as char)
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -137,6 +358,32 @@ mod tests {
         assert!(res.is_err());
     }
 
+    #[test]
+    fn test_error_position() {
+        const ENCODED: &str = "<~9jqo^\n  Blb\u{a0}D-~>";
+        let err = decode_ascii85_str(ENCODED).unwrap_err();
+
+        assert_eq!(err.kind(), DecodeErrorKind::InvalidCharacter('\u{a0}'));
+        assert_eq!(
+            err.position(),
+            Position {
+                offset: 13,
+                line: 2,
+                column: 6
+            }
+        );
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let kind = |s| decode_ascii85_str(s).unwrap_err().kind();
+
+        assert_eq!(kind("  9jqo^~>"), DecodeErrorKind::MissingStartTag);
+        assert_eq!(kind("<~9jqo^"), DecodeErrorKind::MissingEndTag);
+        assert_eq!(kind("<~9jqo^ ~"), DecodeErrorKind::InvalidCharacter('~'));
+        assert_eq!(kind("<~9jqo^~> x"), DecodeErrorKind::InvalidCharacter('x'));
+    }
+
     #[test]
     fn test_decode_sequence() {
         assert_eq!(
@@ -145,8 +392,150 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_strict_mode() {
+        let strict = DecodeOptions { mode: Mode::Strict };
+        let kind = |s| decode_ascii85_with_options(s, strict).unwrap_err().kind();
+
+        assert_eq!(decode_ascii85_str("<~9jqo^B~>").unwrap(), b"Man ");
+        assert_eq!(kind("<~9jqo^B~>"), DecodeErrorKind::TruncatedGroup);
+        assert_eq!(kind("<~9jqo^ B \n ~>"), DecodeErrorKind::TruncatedGroup);
+
+        assert_eq!(
+            decode_ascii85_str("<~9jqo^/cz~>").unwrap(),
+            b"Man .\0\0\0\0"
+        );
+        assert_eq!(kind("<~9jqo^/cz~>"), DecodeErrorKind::ZInsideGroup);
+        assert_eq!(kind("<~9jqo^/c z~>"), DecodeErrorKind::ZInsideGroup);
+
+        assert_eq!(
+            decode_ascii85_with_options("<~9jqo^/c~>", strict).unwrap(),
+            b"Man ."
+        );
+        assert_eq!(
+            decode_ascii85_with_options("<~9jqo^z/c~>", strict).unwrap(),
+            b"Man \0\0\0\0."
+        );
+    }
+
+    #[test]
+    fn test_strict_mode_position() {
+        let strict = DecodeOptions { mode: Mode::Strict };
+        let err = decode_ascii85_with_options("<~9jqo^\n/c z~>", strict).unwrap_err();
+
+        assert_eq!(err.kind(), DecodeErrorKind::ZInsideGroup);
+        assert_eq!(err.position().line, 2);
+        assert_eq!(err.position().column, 4);
+    }
+
+    #[test]
+    fn test_decode_group_boundary() {
+        let digits = |mut n: u64| {
+            let mut vals = [0u32; 5];
+            for v in vals.iter_mut().rev() {
+                *v = (n % 85) as u32;
+                n /= 85;
+            }
+            vals
+        };
+
+        let max = u32::MAX as u64;
+        for n in max - 256..=max {
+            assert_eq!(decode_group(&digits(n)), Ok((n as u32).to_be_bytes()));
+        }
+        for n in max + 1..max + 256 {
+            assert_eq!(
+                decode_group(&digits(n)),
+                Err(DecodeErrorKind::GroupOverflow)
+            );
+        }
+        assert_eq!(
+            decode_group(&[84, 84, 84, 84, 84]),
+            Err(DecodeErrorKind::GroupOverflow)
+        );
+    }
+
+    #[test]
+    fn test_decode_sequence_overflow() {
+        assert_eq!(
+            decode_sequence(&['s', '8', 'W', '-', '!']).unwrap(),
+            vec![0xff, 0xff, 0xff, 0xff]
+        );
+        assert_eq!(
+            decode_sequence(&['s', '8', 'W', '-', '"']),
+            Err(DecodeErrorKind::GroupOverflow)
+        );
+        assert_eq!(
+            decode_sequence(&['s', '8', 'W', '.', '!']),
+            Err(DecodeErrorKind::GroupOverflow)
+        );
+        assert_eq!(
+            decode_sequence(&['u', 'u', 'u', 'u', 'u']),
+            Err(DecodeErrorKind::GroupOverflow)
+        );
+
+        // partial groups are padded with `u`
+        assert_eq!(
+            decode_sequence(&['s', '8', 'W', '*']).unwrap(),
+            vec![0xff; 3]
+        );
+        assert_eq!(
+            decode_sequence(&['s', '8', 'W', '-']),
+            Err(DecodeErrorKind::GroupOverflow)
+        );
+        assert_eq!(
+            decode_sequence(&['u', 'u']),
+            Err(DecodeErrorKind::GroupOverflow)
+        );
+    }
+
+    #[test]
+    fn test_overflow_position() {
+        let err = decode_ascii85_str("<~9jqo^\n uuuuu~>").unwrap_err();
+
+        assert_eq!(err.kind(), DecodeErrorKind::GroupOverflow);
+        assert_eq!(
+            err.position(),
+            Position {
+                offset: 9,
+                line: 2,
+                column: 2
+            }
+        );
+    }
+
     #[test]
     fn test_decode_sequence_padded() {
         assert_eq!(decode_sequence(&['/', 'c']).unwrap(), vec![b'.']);
     }
+
+    #[test]
+    fn test_encode_ascii85() {
+        const DECODED: &str = "Man is distinguished, not only by his reason, but by this singular passion from other animals, which is a lust of the mind, that by a perseverance of delight in the continued and indefatigable generation of knowledge, exceeds the short vehemence of any carnal pleasure.";
+        const ENCODED: &str = r#"<~9jqo^BlbD-BleB1DJ+*+F(f,q/0JhKF<GL>Cj@.4Gp$d7F!,L7@<6@)/0JDEF<G%<+EV:2F!,O<DJ+*.@<*K0@<6L(Df-\0Ec5e;DffZ(EZee.Bl.9pF"AGXBPCsi+DGm>@3BB/F*&OCAfu2/AKYi(DIb:@FD,*)+C]U=@3BN#EcYf8ATD3s@q?d$AftVqCh[NqF<G:8+EV:.+Cf>-FD5W8ARlolDIal(DId<j@<?3r@:F%a+D58'ATD4$Bl@l3De:,-DJs`8ARoFb/0JMK@qB4^F!,R<AKZ&-DfTqBG%G>uD.RTpAKYo'+CT/5+Cei#DII?(E,9)oF*2M7/c~>"#;
+
+        assert_eq!(encode_ascii85(DECODED.as_bytes()), ENCODED);
+    }
+
+    #[test]
+    fn test_encode_z() {
+        assert_eq!(encode_ascii85(&[0, 0, 0, 0]), "<~z~>");
+        assert_eq!(encode_ascii85(&[0, 0, 0]), "<~!!!!~>");
+        assert_eq!(encode_ascii85(&[]), "<~~>");
+    }
+
+    #[test]
+    fn test_encode_sequence_padded() {
+        assert_eq!(encode_sequence(b"."), vec!['/', 'c']);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = (0..=255u8).chain([0, 0, 0, 0, 0, 0, 0]).collect::<Vec<_>>();
+
+        for len in 0..data.len() {
+            let encoded = encode_ascii85(&data[..len]);
+            assert_eq!(decode_ascii85_str(&encoded).unwrap(), &data[..len]);
+        }
+    }
 }
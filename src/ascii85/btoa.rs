@@ -0,0 +1,317 @@
+//! Framing of the historical `btoa` tool.
+//!
+//! The encoded data sits between an `xbtoa Begin` line and a trailer line
+//! giving its length and three checksums:
+//!
+//! `xbtoa End N <length> <length in hex> E <xor> S <sum> R <rotate>`
+//!
+//! Besides `z`, the body uses `y` as a shortcut for four spaces, and the
+//! final group is padded with zeroes instead of being truncated.
+
+use super::{
+    encode_group, sequences, ws, DecodeError, DecodeErrorKind, DecodeOptions, Dialect, ParseError,
+    ParseResult,
+};
+use nom::bytes::complete::tag;
+use nom::character::complete::{digit1, hex_digit1};
+use nom::sequence::{preceded, tuple};
+use nom::Finish;
+use std::fmt::Write;
+
+const BEGIN: &str = "xbtoa Begin";
+const END: &str = "xbtoa End";
+
+/// Number of characters per line, as written by `btoa`.
+const LINE_WIDTH: usize = 78;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Checksums {
+    eor: u32,
+    sum: u32,
+    rot: u32,
+}
+
+impl Checksums {
+    fn of(b: &[u8]) -> Self {
+        let mut ck = Self::default();
+        for &c in b {
+            let c = c as u32;
+            ck.eor ^= c;
+            ck.sum = ck.sum.wrapping_add(c + 1);
+            ck.rot = ck.rot.rotate_left(1).wrapping_add(c);
+        }
+        ck
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Trailer {
+    len: usize,
+    checksums: Checksums,
+}
+
+fn number(radix: u32) -> impl FnMut(&str) -> ParseResult<'_, u32> {
+    move |i| {
+        let (rest, digits) = if radix == 10 {
+            digit1(i)?
+        } else {
+            hex_digit1(i)?
+        };
+        u32::from_str_radix(digits, radix)
+            .map(|n| (rest, n))
+            .map_err(|_| {
+                nom::Err::Error(ParseError {
+                    input: i,
+                    kind: DecodeErrorKind::InvalidTrailer,
+                })
+            })
+    }
+}
+
+fn trailer(i: &str) -> ParseResult<'_, Trailer> {
+    let fields = tuple((
+        preceded(tag(" N "), number(10)),
+        preceded(tag(" "), number(16)),
+        preceded(tag(" E "), number(16)),
+        preceded(tag(" S "), number(16)),
+        preceded(tag(" R "), number(16)),
+    ));
+    let invalid = || {
+        nom::Err::Failure(ParseError {
+            input: i,
+            kind: DecodeErrorKind::InvalidTrailer,
+        })
+    };
+
+    let (rest, (len, len_hex, eor, sum, rot)) =
+        preceded(tag(END), fields)(i).map_err(|_| invalid())?;
+    if len != len_hex {
+        return Err(invalid());
+    }
+
+    let checksums = Checksums { eor, sum, rot };
+    Ok((
+        rest,
+        Trailer {
+            len: len as usize,
+            checksums,
+        },
+    ))
+}
+
+/// Parse the body and trailer, also returning the input at the trailer.
+fn parse_btoa(i: &str, options: DecodeOptions) -> ParseResult<'_, (Vec<u8>, Trailer, &str)> {
+    let (i, _) = ws(i)?;
+    let (i, _) = tag(BEGIN)(i).map_err(|e: nom::Err<ParseError>| {
+        e.map(|_| ParseError {
+            input: i,
+            kind: DecodeErrorKind::MissingStartTag,
+        })
+    })?;
+
+    let (i, data) = sequences(options, Dialect::Btoa)(i)?;
+
+    let (i, _) = ws(i)?;
+    if !i.starts_with(END) {
+        return Err(nom::Err::Error(ParseError::unexpected(
+            i,
+            DecodeErrorKind::MissingEndTag,
+        )));
+    }
+    let (rest, trailer) = trailer(i)?;
+
+    let (rest, _) = ws(rest)?;
+    if !rest.is_empty() {
+        return Err(nom::Err::Error(ParseError::unexpected(
+            rest,
+            DecodeErrorKind::MissingEndTag,
+        )));
+    }
+
+    Ok((rest, (data, trailer, i)))
+}
+
+pub fn decode_btoa(s: &str) -> Result<Vec<u8>, DecodeError> {
+    decode_btoa_with_options(s, DecodeOptions::default())
+}
+
+/// Decode a btoa file, verifying its length and checksums.
+pub fn decode_btoa_with_options(s: &str, options: DecodeOptions) -> Result<Vec<u8>, DecodeError> {
+    let (_, (mut data, trailer, at)) = parse_btoa(s, options)
+        .finish()
+        .map_err(|e| DecodeError::at(e.kind, s, s.len() - e.input.len()))?;
+    let err = |kind| DecodeError::at(kind, s, s.len() - at.len());
+
+    // the final group is complete, and the padding is dropped
+    if data.len() != trailer.len.div_ceil(4) * 4 {
+        return Err(err(DecodeErrorKind::LengthMismatch));
+    }
+    data.truncate(trailer.len);
+
+    if Checksums::of(&data) != trailer.checksums {
+        return Err(err(DecodeErrorKind::ChecksumMismatch));
+    }
+
+    Ok(data)
+}
+
+pub fn encode_btoa(b: &[u8]) -> String {
+    let mut s = String::from(BEGIN);
+    s.push('\n');
+
+    let mut column = 0;
+    for chunk in b.chunks(4) {
+        // pad the final group with zeroes instead of truncating it
+        let mut group = [0u8; 4];
+        group[..chunk.len()].copy_from_slice(chunk);
+
+        let chars = match group {
+            [0, 0, 0, 0] => vec!['z'],
+            [b' ', b' ', b' ', b' '] => vec!['y'],
+            _ => encode_group(&group)
+                .iter()
+                .map(|&d| (d + 33) as char)
+                .collect(),
+        };
+
+        for c in chars {
+            s.push(c);
+            column += 1;
+            if column == LINE_WIDTH {
+                s.push('\n');
+                column = 0;
+            }
+        }
+    }
+    if column != 0 {
+        s.push('\n');
+    }
+
+    let ck = Checksums::of(b);
+    let _ = writeln!(
+        s,
+        "{} N {} {:x} E {:x} S {:x} R {:x}",
+        END,
+        b.len(),
+        b.len(),
+        ck.eor,
+        ck.sum,
+        ck.rot
+    );
+
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCODED: &str = "xbtoa Begin\n@/p9-\nxbtoa End N 1 1 E 61 S 62 R 61\n";
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(
+            Checksums::of(b"a"),
+            Checksums {
+                eor: 0x61,
+                sum: 0x62,
+                rot: 0x61
+            }
+        );
+
+        // the rotation carries the high bit over
+        let mut b = vec![0xc0];
+        b.extend_from_slice(&[0; 24]);
+        b.push(0xff);
+        assert_eq!(
+            Checksums::of(&b),
+            Checksums {
+                eor: 0x3f,
+                sum: 0x1d9,
+                rot: 0x80000100
+            }
+        );
+    }
+
+    #[test]
+    fn test_encode_btoa() {
+        assert_eq!(encode_btoa(b"a"), ENCODED);
+        assert_eq!(
+            encode_btoa(&[0, 0, 0, 0, b' ', b' ', b' ', b' ', 0]),
+            "xbtoa Begin\nzyz\nxbtoa End N 9 9 E 0 S 89 R 3c0\n"
+        );
+        assert_eq!(
+            encode_btoa(b""),
+            "xbtoa Begin\nxbtoa End N 0 0 E 0 S 0 R 0\n"
+        );
+    }
+
+    #[test]
+    fn test_decode_btoa() {
+        assert_eq!(decode_btoa(ENCODED).unwrap(), b"a");
+        assert_eq!(
+            decode_btoa("xbtoa Begin\nzy\nyz\nxbtoa End N 16 10 E 0 S 110 R 1fe00\n").unwrap(),
+            b"\0\0\0\0        \0\0\0\0"
+        );
+    }
+
+    #[test]
+    fn test_line_width() {
+        let data = (0..=255u8).collect::<Vec<_>>();
+        let encoded = encode_btoa(&data);
+        let lines = encoded.lines().collect::<Vec<_>>();
+
+        assert!(lines[1..lines.len() - 2].iter().all(|l| l.len() == 78));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = (0..=255u8)
+            .chain([0, 0, 0, 0, b' ', b' ', b' ', b' ', 0])
+            .collect::<Vec<_>>();
+
+        for len in 0..data.len() {
+            let encoded = encode_btoa(&data[..len]);
+            assert_eq!(decode_btoa(&encoded).unwrap(), &data[..len]);
+        }
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let kind = |s| decode_btoa(s).unwrap_err().kind();
+
+        assert_eq!(
+            kind("@/p9-\nxbtoa End N 1 1 E 61 S 62 R 61\n"),
+            DecodeErrorKind::MissingStartTag
+        );
+        assert_eq!(kind("xbtoa Begin\n@/p9-\n"), DecodeErrorKind::MissingEndTag);
+        assert_eq!(
+            kind("xbtoa Begin\n@/p9-\nxbtoa End N 1 2 E 61 S 62 R 61\n"),
+            DecodeErrorKind::InvalidTrailer
+        );
+        assert_eq!(
+            kind("xbtoa Begin\n@/p9-\nxbtoa End N 1 1 E 61 S 62\n"),
+            DecodeErrorKind::InvalidTrailer
+        );
+        assert_eq!(
+            kind("xbtoa Begin\n@/p9-\nxbtoa End N 5 5 E 61 S 62 R 61\n"),
+            DecodeErrorKind::LengthMismatch
+        );
+        assert_eq!(
+            kind("xbtoa Begin\nA/p9-\nxbtoa End N 1 1 E 61 S 62 R 61\n"),
+            DecodeErrorKind::ChecksumMismatch
+        );
+        assert_eq!(
+            kind("xbtoa Begin\n@/p9-\nxbtoa End N 1 1 E 61 S 62 R 60\n"),
+            DecodeErrorKind::ChecksumMismatch
+        );
+    }
+
+    #[test]
+    fn test_error_position() {
+        let err = decode_btoa("xbtoa Begin\n@/p9-\nxbtoa End N 1 1 E 61 S 62 R 60\n").unwrap_err();
+
+        assert_eq!(err.position().line, 3);
+        assert_eq!(err.position().column, 1);
+    }
+}
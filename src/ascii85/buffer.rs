@@ -0,0 +1,369 @@
+//! Byte-oriented decoding into caller-provided buffers.
+//!
+//! This is a table-driven fast path for the parser, which it mirrors
+//! exactly, errors included.
+
+use super::{decode_group, DecodeError, DecodeErrorKind, DecodeOptions, Mode};
+
+const WS: u8 = 0x80;
+const Z: u8 = 0x81;
+const TILDE: u8 = 0x82;
+const INVALID: u8 = 0xff;
+
+/// Class of every byte: its digit value for Ascii85 characters, or one of
+/// the markers above.
+const TABLE: [u8; 256] = {
+    let mut t = [INVALID; 256];
+    let mut b = b'!';
+    while b <= b'u' {
+        t[b as usize] = b - b'!';
+        b += 1;
+    }
+    t[b' ' as usize] = WS;
+    t[b'\t' as usize] = WS;
+    t[b'\r' as usize] = WS;
+    t[b'\n' as usize] = WS;
+    t[b'z' as usize] = Z;
+    t[b'~' as usize] = TILDE;
+    t
+};
+
+/// Whitespace skipped between characters, as in the parser.
+fn is_ws(b: u8) -> bool {
+    TABLE[b as usize] == WS
+}
+
+/// Strip whitespace and delimiters around a payload, if present.
+fn payload_body(s: &str) -> &str {
+    let s = s.trim_matches(|c: char| c.is_ascii() && is_ws(c as u8));
+    let s = s.strip_prefix("<~").unwrap_or(s);
+    s.strip_suffix("~>").unwrap_or(s)
+}
+
+/// Compute the length of the data encoded in an Ascii85 payload.
+///
+/// The result is exact for valid payloads, and never less than what
+/// [`decode_into`] writes before failing on invalid ones.
+pub fn decoded_len(s: &str) -> usize {
+    let mut len = 0;
+    let mut group = 0;
+
+    for b in payload_body(s).bytes() {
+        match TABLE[b as usize] {
+            Z => {
+                len += 4 + group.max(1) - 1;
+                group = 0;
+            }
+            d if d < 85 => {
+                group += 1;
+                if group == 5 {
+                    len += 4;
+                    group = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    len + group.max(1) - 1
+}
+
+/// Output buffer being filled.
+struct Output<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Output<'_> {
+    fn push(&mut self, b: &[u8]) -> Result<(), DecodeErrorKind> {
+        let dest = self
+            .buf
+            .get_mut(self.len..self.len + b.len())
+            .ok_or(DecodeErrorKind::BufferTooSmall)?;
+        dest.copy_from_slice(b);
+        self.len += b.len();
+        Ok(())
+    }
+}
+
+/// Decode an Ascii85 payload into a buffer, returning the decoded length.
+///
+/// Use [`decoded_len`] to size the buffer.
+pub fn decode_into(s: &str, buf: &mut [u8]) -> Result<usize, DecodeError> {
+    decode_into_with_options(s, buf, DecodeOptions::default())
+}
+
+pub fn decode_into_with_options(
+    s: &str,
+    buf: &mut [u8],
+    options: DecodeOptions,
+) -> Result<usize, DecodeError> {
+    let bytes = s.as_bytes();
+    let err = |kind, offset| DecodeError::at(kind, s, offset);
+    let invalid = |offset: usize| {
+        let c = s[offset..].chars().next().unwrap_or_default();
+        err(DecodeErrorKind::InvalidCharacter(c), offset)
+    };
+    let skip_ws = |i: usize| {
+        i + bytes[i..]
+            .iter()
+            .position(|&b| !is_ws(b))
+            .unwrap_or(bytes.len() - i)
+    };
+
+    let mut out = Output { buf, len: 0 };
+    let mut group = [0u32; 5];
+    let mut n = 0;
+    let mut group_start = 0;
+
+    // decode the pending partial group, padded with `u`
+    let flush = |out: &mut Output, group: &mut [u32; 5], n: usize, start: usize| {
+        if n > 0 {
+            group[n..].fill(84);
+            let b = decode_group(group).map_err(|kind| err(kind, start))?;
+            out.push(&b[..n - 1]).map_err(|kind| err(kind, start))?;
+        }
+        Ok(())
+    };
+
+    let mut i = skip_ws(0);
+    if !bytes[i..].starts_with(b"<~") {
+        return Err(err(DecodeErrorKind::MissingStartTag, i));
+    }
+    i += 2;
+
+    loop {
+        // fast path, decode runs of complete groups at once
+        if n == 0 {
+            while let Some(chunk) = bytes.get(i..i + 5) {
+                let mut digits = [0u32; 5];
+                for (d, &b) in digits.iter_mut().zip(chunk) {
+                    *d = TABLE[b as usize] as u32;
+                }
+                if digits.iter().any(|&d| d >= 85) {
+                    break;
+                }
+
+                let b = decode_group(&digits).map_err(|kind| err(kind, i))?;
+                out.push(&b).map_err(|kind| err(kind, i))?;
+                i += 5;
+            }
+        }
+
+        // as in the parser, the pending group is decoded before the
+        // character ending it is checked
+        let Some(&b) = bytes.get(i) else {
+            flush(&mut out, &mut group, n, group_start)?;
+            return Err(err(DecodeErrorKind::MissingEndTag, i));
+        };
+
+        match TABLE[b as usize] {
+            WS => {
+                i = skip_ws(i);
+                continue;
+            }
+            Z => {
+                if options.mode == Mode::Strict && n > 0 {
+                    return Err(err(DecodeErrorKind::ZInsideGroup, i));
+                }
+                flush(&mut out, &mut group, n, group_start)?;
+                n = 0;
+                out.push(&[0, 0, 0, 0]).map_err(|kind| err(kind, i))?;
+            }
+            TILDE => {
+                let end = bytes.get(i + 1) == Some(&b'>');
+                if options.mode == Mode::Strict && n == 1 && end {
+                    return Err(err(DecodeErrorKind::TruncatedGroup, group_start));
+                }
+                flush(&mut out, &mut group, n, group_start)?;
+                if !end {
+                    return Err(invalid(i));
+                }
+                i += 2;
+                break;
+            }
+            d if d < 85 => {
+                if n == 0 {
+                    group_start = i;
+                }
+                group[n] = d as u32;
+                n += 1;
+                if n == 5 {
+                    let b = decode_group(&group).map_err(|kind| err(kind, group_start))?;
+                    out.push(&b).map_err(|kind| err(kind, group_start))?;
+                    n = 0;
+                }
+            }
+            _ => {
+                flush(&mut out, &mut group, n, group_start)?;
+                return Err(invalid(i));
+            }
+        }
+
+        i += 1;
+    }
+
+    // only whitespace may follow the payload
+    let i = skip_ws(i);
+    if i < bytes.len() {
+        return Err(invalid(i));
+    }
+
+    Ok(out.len)
+}
+
+/// Decode an Ascii85 payload with the byte-oriented decoder.
+///
+/// This gives the same results as [`decode_ascii85_str`], only faster.
+///
+/// [`decode_ascii85_str`]: super::decode_ascii85_str
+pub fn decode_ascii85_fast(s: &str) -> Result<Vec<u8>, DecodeError> {
+    decode_ascii85_fast_with_options(s, DecodeOptions::default())
+}
+
+pub fn decode_ascii85_fast_with_options(
+    s: &str,
+    options: DecodeOptions,
+) -> Result<Vec<u8>, DecodeError> {
+    let mut buf = vec![0; decoded_len(s)];
+    let len = decode_into_with_options(s, &mut buf, options)?;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii85::{decode_ascii85_str, decode_ascii85_with_options, encode_ascii85};
+
+    fn decode(s: &str, options: DecodeOptions) -> Result<Vec<u8>, DecodeError> {
+        let mut buf = vec![0; decoded_len(s)];
+        let len = decode_into_with_options(s, &mut buf, options)?;
+        assert_eq!(len, buf.len());
+        Ok(buf)
+    }
+
+    #[test]
+    fn test_decode_into() {
+        let mut buf = [0u8; 16];
+
+        assert_eq!(decode_into("<~9jqo^/c~>", &mut buf), Ok(5));
+        assert_eq!(&buf[..5], b"Man .");
+
+        assert_eq!(decode_into(" <~ z\n9jqo^ ~>\n", &mut buf), Ok(8));
+        assert_eq!(&buf[..8], b"\0\0\0\0Man ");
+    }
+
+    #[test]
+    fn test_buffer_too_small() {
+        let mut buf = [0u8; 4];
+        let err = decode_into("<~9jqo^/c~>", &mut buf).unwrap_err();
+
+        assert_eq!(err.kind(), DecodeErrorKind::BufferTooSmall);
+        assert_eq!(err.position().offset, 7);
+    }
+
+    #[test]
+    fn test_payload_body() {
+        assert_eq!(payload_body(" \n<~9jqo^~>\n"), "9jqo^");
+        // non-ASCII characters are not whitespace, whatever their low byte
+        assert_eq!(
+            payload_body("\u{120}<~9jqo^~>\u{10a}"),
+            "\u{120}<~9jqo^~>\u{10a}"
+        );
+    }
+
+    #[test]
+    fn test_decoded_len() {
+        assert_eq!(decoded_len("<~~>"), 0);
+        assert_eq!(decoded_len("<~9jqo^~>"), 4);
+        assert_eq!(decoded_len("<~9jqo^/c~>"), 5);
+        assert_eq!(decoded_len("<~9jqo^/cz~>"), 9);
+        assert_eq!(decoded_len("<~9jqo^Bz~>"), 8);
+        assert_eq!(decoded_len("  <~9jq\no^ ~>\n"), 4);
+    }
+
+    #[test]
+    fn test_same_as_parser() {
+        let inputs = [
+            r#"
+            <~9jqo^BlbD-BleB1DJ+*+F(f,q/0JhKF<GL>Cj@.4Gp$d7F!,L7@<6@)/0JDEF<G%<+EV:2F!,
+            O<DJ+*.@<*K0@<6L(Df-\0Ec5e;DffZ(EZee.Bl.9pF"AGXBPCsi+DGm>@3BB/F*&OCAfu2/AKY
+            i(DIb:@FD,*)+C]U=@3BN#EcYf8ATD3s@q?d$AftVqCh[NqF<G:8+EV:.+Cf>-FD5W8ARlolDIa
+            l(DId<j@<?3r@:F%a+D58'ATD4$Bl@l3De:,-DJs`8ARoFb/0JMK@qB4^F!,R<AKZ&-DfTqBG%G
+            >uD.RTpAKYo'+CT/5+Cei#DII?(E,9)oF*2M7/c~>
+        "#,
+            "<~z~>",
+            "<~~>",
+            "<~àç_èé'(è~>",
+            "<~9jqo^/c~>",
+            "<~9jqo^ B \n ~>",
+            "<~9jqo^z/c~>",
+            "<~9jqo^\n/c z~>",
+            "<~9jqo^\n uuuuu~>",
+            "<~9jzqo^~>",
+            "  9jqo^~>",
+            "<~9jqo^B~>",
+            "<~9jqo^/cz~>",
+            "<~9jqo^/c z~>",
+            "<~9jqo^uuuuu~>",
+            "<~9jqo^ ~",
+            "<~9jqo^~> x",
+            "9jqo^~>",
+            "<~9jqo^\n  Blb\u{a0}D-~>",
+            "<~9jqo^",
+            "<~ux!u/c~>",
+            "<~zuW",
+            "<~z/~",
+        ];
+
+        for options in [
+            DecodeOptions::default(),
+            DecodeOptions { mode: Mode::Strict },
+        ] {
+            for s in inputs {
+                assert_eq!(
+                    decode(s, options),
+                    decode_ascii85_with_options(s, options),
+                    "{:?}",
+                    s
+                );
+                assert_eq!(
+                    decode_ascii85_fast_with_options(s, options),
+                    decode_ascii85_with_options(s, options),
+                    "{:?}",
+                    s
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = (0..=255u8).chain([0, 0, 0, 0, 0]).collect::<Vec<_>>();
+
+        for len in 0..data.len() {
+            let encoded = encode_ascii85(&data[..len]);
+            assert_eq!(
+                decode(&encoded, DecodeOptions::default()).unwrap(),
+                &data[..len]
+            );
+            assert_eq!(decode_ascii85_fast(&encoded).unwrap(), &data[..len]);
+        }
+    }
+
+    #[test]
+    fn test_layer_file() {
+        const LAYER: &str = include_str!("../../layers/00.txt");
+        let payload = &LAYER[LAYER.find("<~").unwrap()..];
+
+        assert_eq!(
+            decode(payload, DecodeOptions::default()).unwrap(),
+            decode_ascii85_str(payload).unwrap()
+        );
+        assert_eq!(
+            decode_ascii85_fast(payload).unwrap(),
+            decode_ascii85_str(payload).unwrap()
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeErrorKind {
+    /// A character outside of the alphabet.
+    InvalidCharacter(char),
+    /// A group whose value does not fit in 32 bits.
+    GroupOverflow,
+    /// A final group too short to hold any data.
+    TruncatedGroup,
+    /// The payload does not start with its delimiter, such as `<~`.
+    MissingStartTag,
+    /// The payload does not end with its delimiter, such as `~>`.
+    MissingEndTag,
+    /// The `z` shortcut in the middle of a group.
+    ZInsideGroup,
+    /// The output buffer cannot hold the decoded data.
+    BufferTooSmall,
+    /// A btoa trailer line that cannot be parsed.
+    InvalidTrailer,
+    /// Decoded data whose length differs from the one announced.
+    LengthMismatch,
+    /// Decoded data whose checksums differ from the ones announced.
+    ChecksumMismatch,
+}
+
+impl Display for DecodeErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeErrorKind::InvalidCharacter(c) => write!(f, "invalid character {:?}", c),
+            DecodeErrorKind::GroupOverflow => write!(f, "group value overflows 32 bits"),
+            DecodeErrorKind::TruncatedGroup => write!(f, "truncated final group"),
+            DecodeErrorKind::MissingStartTag => write!(f, "missing start delimiter"),
+            DecodeErrorKind::MissingEndTag => write!(f, "missing end delimiter"),
+            DecodeErrorKind::ZInsideGroup => write!(f, "`z` inside a group"),
+            DecodeErrorKind::BufferTooSmall => write!(f, "output buffer too small"),
+            DecodeErrorKind::InvalidTrailer => write!(f, "invalid trailer"),
+            DecodeErrorKind::LengthMismatch => write!(f, "decoded length mismatch"),
+            DecodeErrorKind::ChecksumMismatch => write!(f, "checksum mismatch"),
+        }
+    }
+}
+
+/// Location of an error in the decoded text.
+///
+/// The offset is counted in bytes from 0, lines and columns in characters
+/// from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl Position {
+    /// Compute the position of a byte offset in a string.
+    pub fn locate(s: &str, offset: usize) -> Self {
+        let before = &s[..offset];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        Self {
+            offset,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+
+    /// Move past a byte of UTF-8 text.
+    pub(crate) fn advance(&mut self, b: u8) {
+        self.offset += 1;
+        if b == b'\n' {
+            self.line += 1;
+            self.column = 1;
+        } else if b & 0xC0 != 0x80 {
+            // continuation bytes do not start a new character
+            self.column += 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    kind: DecodeErrorKind,
+    position: Position,
+}
+
+impl DecodeError {
+    pub fn new(kind: DecodeErrorKind, position: Position) -> Self {
+        Self { kind, position }
+    }
+
+    /// Create an error located at a byte offset of the decoded text.
+    pub(crate) fn at(kind: DecodeErrorKind, s: &str, offset: usize) -> Self {
+        Self::new(kind, Position::locate(s, offset))
+    }
+
+    pub fn kind(&self) -> DecodeErrorKind {
+        self.kind
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}",
+            self.kind, self.position.line, self.position.column
+        )
+    }
+}
+
+impl Error for DecodeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locate() {
+        const S: &str = "<~ab\ncdé\nf";
+
+        assert_eq!(
+            Position::locate(S, 0),
+            Position {
+                offset: 0,
+                line: 1,
+                column: 1
+            }
+        );
+        assert_eq!(
+            Position::locate(S, 5),
+            Position {
+                offset: 5,
+                line: 2,
+                column: 1
+            }
+        );
+        assert_eq!(
+            Position::locate(S, 10),
+            Position {
+                offset: 10,
+                line: 3,
+                column: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_advance() {
+        const S: &str = "<~ab\ncdé\nf";
+
+        let mut p = Position::locate(S, 0);
+        for b in S.bytes() {
+            p.advance(b);
+        }
+
+        assert_eq!(p, Position::locate(S, S.len()));
+    }
+}
@@ -0,0 +1,266 @@
+use super::{
+    decode_sequence, is_ascii85_value, DecodeError, DecodeErrorKind, DecodeOptions, Mode, Position,
+};
+use std::io;
+use std::io::{BufRead, ErrorKind, Read};
+
+enum State {
+    Start,
+    StartTag,
+    Body,
+    EndTag,
+    Done,
+}
+
+/// Incremental Ascii85 decoder.
+///
+/// Reads a `<~ ... ~>` payload from the underlying reader and yields the
+/// decoded bytes, keeping at most one group in memory.
+/// Anything following the closing `~>` is left unread.
+pub struct Ascii85Reader<R> {
+    inner: R,
+    options: DecodeOptions,
+    state: State,
+    group: Vec<char>,
+    group_start: Position,
+    position: Position,
+    decoded: Vec<u8>,
+    pos: usize,
+}
+
+impl<R> Ascii85Reader<R>
+where
+    R: BufRead,
+{
+    pub fn new(inner: R) -> Self {
+        Self::with_options(inner, DecodeOptions::default())
+    }
+
+    pub fn with_options(inner: R, options: DecodeOptions) -> Self {
+        Self {
+            inner,
+            options,
+            state: State::Start,
+            group: Vec::with_capacity(5),
+            group_start: Position::default(),
+            position: Position::default(),
+            decoded: Vec::with_capacity(4),
+            pos: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Feed characters to the decoder until some output is available,
+    /// or the end of the payload is reached.
+    fn fill_decoded(&mut self) -> io::Result<()> {
+        self.decoded.clear();
+        self.pos = 0;
+
+        while self.decoded.is_empty() && !matches!(self.state, State::Done) {
+            let b = match self.inner.fill_buf()?.first() {
+                Some(&b) => b,
+                None => {
+                    let kind = match self.state {
+                        State::Start | State::StartTag => DecodeErrorKind::MissingStartTag,
+                        _ => DecodeErrorKind::MissingEndTag,
+                    };
+                    return Err(self.error(ErrorKind::UnexpectedEof, kind, self.position));
+                }
+            };
+
+            let position = self.position;
+            self.inner.consume(1);
+            self.position.advance(b);
+
+            self.state = match self.state {
+                State::Start if b.is_ascii_whitespace() => State::Start,
+                State::Start if b == b'<' => State::StartTag,
+                State::StartTag if b == b'~' => State::Body,
+                State::Body if b.is_ascii_whitespace() => State::Body,
+                State::Body if b == b'~' => {
+                    if self.options.mode == Mode::Strict && self.group.len() == 1 {
+                        return Err(
+                            self.invalid_data(DecodeErrorKind::TruncatedGroup, self.group_start)
+                        );
+                    }
+                    self.flush_group()?;
+                    State::EndTag
+                }
+                State::Body if b == b'z' => {
+                    if self.options.mode == Mode::Strict && !self.group.is_empty() {
+                        return Err(self.invalid_data(DecodeErrorKind::ZInsideGroup, position));
+                    }
+                    self.flush_group()?;
+                    self.decoded.extend_from_slice(&[0, 0, 0, 0]);
+                    State::Body
+                }
+                State::Body if is_ascii85_value(b as char) => {
+                    if self.group.is_empty() {
+                        self.group_start = position;
+                    }
+                    self.group.push(b as char);
+                    if self.group.len() == 5 {
+                        self.flush_group()?;
+                    }
+                    State::Body
+                }
+                State::EndTag if b == b'>' => State::Done,
+                State::Start | State::StartTag => {
+                    return Err(self.invalid_data(DecodeErrorKind::MissingStartTag, position));
+                }
+                _ => {
+                    // report the replacement character for non-ASCII bytes
+                    let c = if b.is_ascii() { b as char } else { '\u{fffd}' };
+                    return Err(self.invalid_data(DecodeErrorKind::InvalidCharacter(c), position));
+                }
+            };
+        }
+
+        Ok(())
+    }
+
+    fn flush_group(&mut self) -> io::Result<()> {
+        if !self.group.is_empty() {
+            let bytes = decode_sequence(&self.group)
+                .map_err(|kind| self.invalid_data(kind, self.group_start))?;
+            self.decoded.extend_from_slice(&bytes);
+            self.group.clear();
+        }
+        Ok(())
+    }
+
+    fn error(&self, io_kind: ErrorKind, kind: DecodeErrorKind, position: Position) -> io::Error {
+        io::Error::new(io_kind, DecodeError::new(kind, position))
+    }
+
+    fn invalid_data(&self, kind: DecodeErrorKind, position: Position) -> io::Error {
+        self.error(ErrorKind::InvalidData, kind, position)
+    }
+}
+
+impl<R> Read for Ascii85Reader<R>
+where
+    R: BufRead,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.decoded.len() {
+            self.fill_decoded()?;
+        }
+
+        let available = &self.decoded[self.pos..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii85::{decode_ascii85_str, encode_ascii85};
+    use std::io::BufReader;
+
+    fn read_all(s: &str, capacity: usize) -> io::Result<Vec<u8>> {
+        read_all_with_options(s, capacity, DecodeOptions::default())
+    }
+
+    fn read_all_with_options(
+        s: &str,
+        capacity: usize,
+        options: DecodeOptions,
+    ) -> io::Result<Vec<u8>> {
+        let reader = BufReader::with_capacity(capacity, s.as_bytes());
+        let mut r = Ascii85Reader::with_options(reader, options);
+        let mut out = vec![];
+        r.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_read() {
+        const ENCODED: &str = r#"
+            <~9jqo^BlbD-BleB1DJ+*+F(f,q/0JhKF<GL>Cj@.4Gp$d7F!,L7@<6@)/0JDEF<G%<+EV:2F!,
+            O<DJ+*.@<*K0@<6L(Df-\0Ec5e;DffZ(EZee.Bl.9pF"AGXBPCsi+DGm>@3BB/F*&OCAfu2/AKY
+            i(DIb:@FD,*)+C]U=@3BN#EcYf8ATD3s@q?d$AftVqCh[NqF<G:8+EV:.+Cf>-FD5W8ARlolDIa
+            l(DId<j@<?3r@:F%a+D58'ATD4$Bl@l3De:,-DJs`8ARoFb/0JMK@qB4^F!,R<AKZ&-DfTqBG%G
+            >uD.RTpAKYo'+CT/5+Cei#DII?(E,9)oF*2M7zz/c~>
+        "#;
+
+        let expected = decode_ascii85_str(ENCODED).unwrap();
+
+        assert_eq!(read_all(ENCODED, 1).unwrap(), expected);
+        assert_eq!(read_all(ENCODED, 7).unwrap(), expected);
+        assert_eq!(read_all(ENCODED, 8192).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = (0..=255u8).chain([0, 0, 0, 0, 0]).collect::<Vec<_>>();
+
+        for len in 0..data.len() {
+            let encoded = encode_ascii85(&data[..len]);
+            assert_eq!(read_all(&encoded, 3).unwrap(), &data[..len]);
+        }
+    }
+
+    #[test]
+    fn test_trailing_data_unread() {
+        let mut r = Ascii85Reader::new("<~/c~>rest".as_bytes());
+        let mut out = vec![];
+        r.read_to_end(&mut out).unwrap();
+
+        assert_eq!(out, b".");
+        assert_eq!(r.into_inner(), b"rest");
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = read_all("<~9jqo^", 4).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+        let err = read_all("9jqo^~>", 4).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let err = read_all("<~9jqo^Bl\u{e0}bD~>", 4).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let err = read_all("<~uuuuu~>", 4).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let strict = DecodeOptions { mode: Mode::Strict };
+
+        for s in ["<~9jqo^B~>", "<~9jqo^/cz~>", "<~9jzqo^~>"] {
+            let expected = decode_ascii85_str(s).unwrap();
+            assert_eq!(read_all(s, 4).unwrap(), expected);
+
+            let err = read_all_with_options(s, 4, strict).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_error_position() {
+        let err = read_all("<~9jqo^\n  Blb\u{a0}D-~>", 4).unwrap_err();
+        let err = err.into_inner().unwrap().downcast::<DecodeError>().unwrap();
+
+        assert_eq!(
+            *err,
+            DecodeError::new(
+                DecodeErrorKind::InvalidCharacter('\u{fffd}'),
+                Position {
+                    offset: 13,
+                    line: 2,
+                    column: 6
+                }
+            )
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! Recovery from junk pasted into Ascii85 payloads.
+
+use super::{
+    decode_ascii85_with_options, is_ascii85_value, DecodeError, DecodeErrorKind, DecodeOptions,
+    Position,
+};
+
+/// A character dropped while decoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub position: Position,
+    pub character: char,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recovered {
+    pub data: Vec<u8>,
+    pub skipped: Vec<Diagnostic>,
+}
+
+/// Text with junk removed, remembering where each byte came from.
+struct Cleaned<'a> {
+    source: &'a str,
+    text: String,
+    offsets: Vec<usize>,
+    skipped: Vec<Diagnostic>,
+    cursor: Position,
+}
+
+impl<'a> Cleaned<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            text: String::with_capacity(source.len()),
+            offsets: Vec::with_capacity(source.len()),
+            skipped: vec![],
+            cursor: Position::default(),
+        }
+    }
+
+    fn push(&mut self, offset: usize, c: char) {
+        self.text.push(c);
+        self.offsets.push(offset);
+    }
+
+    /// Copy the characters of a range, dropping the ones not kept.
+    fn filter(&mut self, start: usize, end: usize, keep: impl Fn(char) -> bool) {
+        for (i, c) in self.source[start..end].char_indices() {
+            if keep(c) {
+                self.push(start + i, c);
+            } else {
+                // positions are only ever needed further down the text
+                while self.cursor.offset < start + i {
+                    self.cursor
+                        .advance(self.source.as_bytes()[self.cursor.offset]);
+                }
+                self.skipped.push(Diagnostic {
+                    position: self.cursor,
+                    character: c,
+                });
+            }
+        }
+    }
+
+    /// Offset in the source of an offset in the cleaned text.
+    fn source_offset(&self, offset: usize) -> usize {
+        self.offsets
+            .get(offset)
+            .copied()
+            .unwrap_or(self.source.len())
+    }
+}
+
+fn is_ws(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\r' | '\n')
+}
+
+/// Decode an Ascii85 payload, skipping any character that cannot be part
+/// of it instead of failing.
+///
+/// Junk around the delimiters and inside the payload, a stray `~`
+/// included, is dropped and reported. Other errors, such as a missing
+/// delimiter or an overflowing group, are still fatal.
+pub fn decode_ascii85_recover(s: &str, options: DecodeOptions) -> Result<Recovered, DecodeError> {
+    let start = s.find("<~").ok_or_else(|| {
+        let i = s.len() - s.trim_start_matches(is_ws).len();
+        DecodeError::at(DecodeErrorKind::MissingStartTag, s, i)
+    })?;
+    let end = s[start + 2..].find("~>").map(|i| start + 2 + i);
+
+    let mut cleaned = Cleaned::new(s);
+    cleaned.filter(0, start, is_ws);
+    cleaned.push(start, '<');
+    cleaned.push(start + 1, '~');
+
+    let body_end = end.unwrap_or(s.len());
+    cleaned.filter(start + 2, body_end, |c| {
+        is_ws(c) || c == 'z' || is_ascii85_value(c)
+    });
+
+    if let Some(end) = end {
+        cleaned.push(end, '~');
+        cleaned.push(end + 1, '>');
+        cleaned.filter(end + 2, s.len(), is_ws);
+    }
+
+    let data = decode_ascii85_with_options(&cleaned.text, options)
+        .map_err(|e| DecodeError::at(e.kind(), s, cleaned.source_offset(e.position().offset)))?;
+
+    Ok(Recovered {
+        data,
+        skipped: cleaned.skipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii85::Mode;
+
+    fn skipped(s: &str) -> Vec<(usize, usize, usize, char)> {
+        decode_ascii85_recover(s, DecodeOptions::default())
+            .unwrap()
+            .skipped
+            .into_iter()
+            .map(|d| {
+                let p = d.position;
+                (p.offset, p.line, p.column, d.character)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_input() {
+        let r = decode_ascii85_recover("<~9jqo^/c~>", DecodeOptions::default()).unwrap();
+
+        assert_eq!(r.data, b"Man .");
+        assert!(r.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_skip_junk() {
+        const ENCODED: &str = "“<~9jqo\u{a0}^~\n/c~>”";
+
+        let r = decode_ascii85_recover(ENCODED, DecodeOptions::default()).unwrap();
+
+        assert_eq!(r.data, b"Man .");
+        assert_eq!(
+            skipped(ENCODED),
+            vec![
+                (0, 1, 1, '“'),
+                (9, 1, 8, '\u{a0}'),
+                (12, 1, 10, '~'),
+                (18, 2, 5, '”'),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fatal_errors() {
+        let kind = |s| {
+            decode_ascii85_recover(s, DecodeOptions::default())
+                .unwrap_err()
+                .kind()
+        };
+
+        assert_eq!(kind(" 9jqo^~>"), DecodeErrorKind::MissingStartTag);
+        assert_eq!(kind("<~9jqo^"), DecodeErrorKind::MissingEndTag);
+        assert_eq!(kind("<~9jqo^uuuuu~>"), DecodeErrorKind::GroupOverflow);
+    }
+
+    #[test]
+    fn test_error_position() {
+        let err = decode_ascii85_recover("<~\u{a0}9jqo^\nuu\u{a0}uuu~>", DecodeOptions::default())
+            .unwrap_err();
+
+        assert_eq!(err.kind(), DecodeErrorKind::GroupOverflow);
+        assert_eq!(
+            err.position(),
+            Position {
+                offset: 10,
+                line: 2,
+                column: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let strict = DecodeOptions { mode: Mode::Strict };
+        let err = decode_ascii85_recover("<~9jqo^\u{a0}B~>", strict).unwrap_err();
+
+        assert_eq!(err.kind(), DecodeErrorKind::TruncatedGroup);
+        assert_eq!(err.position().offset, 9);
+    }
+}
@@ -0,0 +1,105 @@
+use super::{decode_ascii85_with_options, DecodeError, DecodeErrorKind, DecodeOptions};
+use std::ops::Range;
+
+/// An Ascii85 block found in a larger text.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Ascii85Block {
+    /// Byte range of the block in the text, delimiters included.
+    pub range: Range<usize>,
+    /// Decoded contents, or the error located relative to the whole text.
+    pub data: Result<Vec<u8>, DecodeError>,
+}
+
+impl Ascii85Block {
+    /// Whether the block is missing its closing delimiter.
+    pub fn is_unterminated(&self) -> bool {
+        matches!(&self.data, Err(e) if e.kind() == DecodeErrorKind::MissingEndTag)
+    }
+}
+
+/// Find and decode every `<~ ... ~>` block of a text.
+///
+/// A block that fails to decode is returned with its error, and scanning
+/// resumes right after its `<~`, so that a stray delimiter in prose does not
+/// hide a real block following it.
+pub fn decode_ascii85_blocks(s: &str, options: DecodeOptions) -> Vec<Ascii85Block> {
+    let mut blocks = vec![];
+    let mut pos = 0;
+
+    while let Some(i) = s[pos..].find("<~") {
+        let start = pos + i;
+        let end = s[start + 2..]
+            .find("~>")
+            .map_or(s.len(), |i| start + 2 + i + 2);
+
+        let data = decode_ascii85_with_options(&s[start..end], options)
+            .map_err(|e| DecodeError::at(e.kind(), s, start + e.position().offset));
+
+        pos = if data.is_ok() { end } else { start + 2 };
+        blocks.push(Ascii85Block {
+            range: start..end,
+            data,
+        });
+    }
+
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_blocks() {
+        const DOC: &str = "%!PS\n<~9jqo^~> some text <~/c~>\nend";
+
+        let blocks = decode_ascii85_blocks(DOC, DecodeOptions::default());
+
+        assert_eq!(
+            blocks,
+            vec![
+                Ascii85Block {
+                    range: 5..14,
+                    data: Ok(b"Man ".to_vec()),
+                },
+                Ascii85Block {
+                    range: 25..31,
+                    data: Ok(b".".to_vec()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stray_delimiters() {
+        const DOC: &str = "write <~ {x}, then\n<~9jqo^~>\n<~/c";
+
+        let blocks = decode_ascii85_blocks(DOC, DecodeOptions::default());
+
+        assert_eq!(blocks.len(), 3);
+
+        let err = blocks[0].data.as_ref().unwrap_err();
+        assert_eq!(blocks[0].range, 6..28);
+        assert_eq!(err.kind(), DecodeErrorKind::InvalidCharacter('{'));
+        assert_eq!(err.position().offset, 9);
+
+        assert_eq!(blocks[1].range, 19..28);
+        assert_eq!(blocks[1].data, Ok(b"Man ".to_vec()));
+
+        assert_eq!(blocks[2].range, 29..33);
+        assert!(blocks[2].is_unterminated());
+        assert_eq!(blocks[2].data.as_ref().unwrap_err().position().line, 3);
+    }
+
+    #[test]
+    fn test_layer_file() {
+        const LAYER: &str = include_str!("../../layers/00.txt");
+
+        let blocks = decode_ascii85_blocks(LAYER, DecodeOptions::default());
+
+        assert_eq!(blocks.len(), 1);
+        assert!(blocks[0].data.is_ok());
+        assert!(LAYER[blocks[0].range.clone()].starts_with("<~4[!!l9OW3X"));
+        assert!(LAYER[blocks[0].range.clone()].ends_with("W:*8$46~>"));
+    }
+}
@@ -0,0 +1,181 @@
+//! Selection between the base 85 flavours found in the wild.
+
+use super::{
+    decode_alphabet, decode_ascii85_str, decode_btoa, decode_z85, encode_alphabet, encode_ascii85,
+    encode_btoa, encode_z85, DecodeError, DecodeErrorKind,
+};
+
+/// Alphabet of RFC 1924, also used by git and Python's `b85encode`.
+const RFC1924_ALPHABET: &[u8; 85] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+/// Maximum number of bytes encoded on a single line of a git binary patch.
+const GIT_LINE_BYTES: usize = 52;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    /// Adobe Ascii85, with `<~ ~>` framing and the `z` shortcut.
+    Adobe,
+    /// ZeroMQ Z85, without partial groups.
+    Z85,
+    /// RFC 1924 alphabet, with a truncated final group.
+    Rfc1924,
+    /// RFC 1924 alphabet split into lines, each prefixed by a character
+    /// giving its decoded length, as found in git binary patches.
+    GitBinaryPatch,
+    /// Adobe alphabet with `xbtoa` framing, as written by the `btoa` tool.
+    Btoa,
+}
+
+pub fn decode_base85(s: &str, variant: Variant) -> Result<Vec<u8>, DecodeError> {
+    match variant {
+        Variant::Adobe => decode_ascii85_str(s),
+        Variant::Z85 => decode_z85(s),
+        Variant::Rfc1924 => decode_alphabet(s, RFC1924_ALPHABET),
+        Variant::GitBinaryPatch => decode_git_binary_patch(s),
+        Variant::Btoa => decode_btoa(s),
+    }
+}
+
+/// Encode a buffer with the given variant.
+///
+/// Returns `None` if the variant cannot represent the buffer, which only
+/// happens for Z85 when its length is not a multiple of 4.
+pub fn encode_base85(b: &[u8], variant: Variant) -> Option<String> {
+    match variant {
+        Variant::Adobe => Some(encode_ascii85(b)),
+        Variant::Z85 => encode_z85(b),
+        Variant::Rfc1924 => Some(encode_alphabet(b, RFC1924_ALPHABET)),
+        Variant::GitBinaryPatch => Some(encode_git_binary_patch(b)),
+        Variant::Btoa => Some(encode_btoa(b)),
+    }
+}
+
+fn decode_git_binary_patch(s: &str) -> Result<Vec<u8>, DecodeError> {
+    let mut result = vec![];
+    let mut offset = 0;
+
+    for line in s.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+
+        let line = line.trim_end();
+        let len = match line.chars().next() {
+            Some(c @ 'A'..='Z') => c as usize - 'A' as usize + 1,
+            Some(c @ 'a'..='z') => c as usize - 'a' as usize + 27,
+            Some(c) => {
+                return Err(DecodeError::at(
+                    DecodeErrorKind::InvalidCharacter(c),
+                    s,
+                    start,
+                ))
+            }
+            None => continue,
+        };
+
+        // lines always contain complete groups
+        let data = &line[1..];
+        if data.chars().count() != len.div_ceil(4) * 5 {
+            return Err(DecodeError::at(DecodeErrorKind::TruncatedGroup, s, start));
+        }
+
+        let bytes = decode_alphabet(data, RFC1924_ALPHABET)
+            .map_err(|e| DecodeError::at(e.kind(), s, start + 1 + e.position().offset))?;
+        result.extend_from_slice(&bytes[..len]);
+    }
+
+    Ok(result)
+}
+
+fn encode_git_binary_patch(b: &[u8]) -> String {
+    let mut s = String::new();
+
+    for line in b.chunks(GIT_LINE_BYTES) {
+        let len = line.len() as u8;
+        s.push(if len <= 26 {
+            (b'A' + len - 1) as char
+        } else {
+            (b'a' + len - 27) as char
+        });
+
+        // pad the final group with zeroes instead of truncating it
+        let mut padded = line.to_vec();
+        padded.resize(line.len().div_ceil(4) * 4, 0);
+        s.push_str(&encode_alphabet(&padded, RFC1924_ALPHABET));
+        s.push('\n');
+    }
+
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VARIANTS: &[Variant] = &[
+        Variant::Adobe,
+        Variant::Z85,
+        Variant::Rfc1924,
+        Variant::GitBinaryPatch,
+        Variant::Btoa,
+    ];
+
+    #[test]
+    fn test_rfc1924() {
+        assert_eq!(
+            encode_base85(b"hello", Variant::Rfc1924).unwrap(),
+            "Xk~0{Zv"
+        );
+        assert_eq!(
+            decode_base85("Xk~0{Zv", Variant::Rfc1924).unwrap(),
+            b"hello"
+        );
+        assert!(decode_base85("Xk\"0{Zv", Variant::Rfc1924).is_err());
+    }
+
+    #[test]
+    fn test_git_binary_patch() {
+        let data = (0..60u8).collect::<Vec<_>>();
+        let encoded = encode_base85(&data, Variant::GitBinaryPatch).unwrap();
+        let lines = encoded.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with('z'));
+        assert_eq!(lines[0].len(), 1 + 13 * 5);
+        assert!(lines[1].starts_with('H'));
+        assert_eq!(lines[1].len(), 1 + 2 * 5);
+
+        assert_eq!(
+            decode_base85(&encoded, Variant::GitBinaryPatch).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn test_git_binary_patch_invalid() {
+        assert!(decode_base85("B00", Variant::GitBinaryPatch).is_err());
+        assert!(decode_base85("!00000", Variant::GitBinaryPatch).is_err());
+
+        let err = decode_base85("A00000\nA00\"00", Variant::GitBinaryPatch).unwrap_err();
+        assert_eq!(err.kind(), DecodeErrorKind::InvalidCharacter('"'));
+        assert_eq!(err.position().line, 2);
+        assert_eq!(err.position().column, 4);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = (0..=255u8).collect::<Vec<_>>();
+
+        for &variant in VARIANTS {
+            for len in 0..data.len() {
+                match encode_base85(&data[..len], variant) {
+                    Some(encoded) => {
+                        let decoded = decode_base85(&encoded, variant).unwrap();
+                        assert_eq!(decoded, &data[..len], "{:?}", variant);
+                    }
+                    None => assert_eq!((variant, len % 4 != 0), (Variant::Z85, true)),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use super::encode_group;
+use std::io;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// Number of characters per line, delimiters included, or `None` to
+    /// write a single line. At least 2, so that a delimiter fits on a line.
+    pub line_width: Option<usize>,
+    /// Write `z` for groups of four zero bytes.
+    pub z_shortcut: bool,
+    /// Frame the output with `<~` and `~>`.
+    pub delimiters: bool,
+}
+
+impl Default for EncodeOptions {
+    /// Options matching the payloads of the layer files.
+    fn default() -> Self {
+        Self {
+            line_width: Some(60),
+            z_shortcut: true,
+            delimiters: true,
+        }
+    }
+}
+
+/// Incremental Ascii85 encoder.
+///
+/// Encodes the data written to it into the underlying writer, keeping at
+/// most one partial group in memory.
+/// The final group and delimiter are only written by [`finish`], which is
+/// called on drop if needed, ignoring errors.
+///
+/// [`finish`]: Ascii85Writer::finish
+pub struct Ascii85Writer<W>
+where
+    W: Write,
+{
+    inner: Option<W>,
+    options: EncodeOptions,
+    group: Vec<u8>,
+    column: usize,
+    started: bool,
+}
+
+impl<W> Ascii85Writer<W>
+where
+    W: Write,
+{
+    pub fn new(inner: W) -> Self {
+        Self::with_options(inner, EncodeOptions::default())
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the line width is less than 2.
+    pub fn with_options(inner: W, options: EncodeOptions) -> Self {
+        assert!(
+            options.line_width.is_none_or(|w| w >= 2),
+            "line width must be at least 2"
+        );
+
+        Self {
+            inner: Some(inner),
+            options,
+            group: Vec::with_capacity(4),
+            column: 0,
+            started: false,
+        }
+    }
+
+    /// Write the final group and delimiter, and return the underlying writer.
+    ///
+    /// The end is only written once: on failure, it is not written again on
+    /// drop.
+    pub fn finish(mut self) -> io::Result<W> {
+        let out = self.end();
+        let mut inner = self.inner.take().expect("writer already finished");
+        inner.write_all(&out)?;
+        inner.flush()?;
+        Ok(inner)
+    }
+
+    /// Encode the final group and delimiter.
+    fn end(&mut self) -> Vec<u8> {
+        let mut out = vec![];
+        self.start(&mut out);
+
+        if !self.group.is_empty() {
+            let group = std::mem::take(&mut self.group);
+            self.encode(&group, &mut out);
+        }
+
+        if self.options.delimiters {
+            // never split the delimiter over two lines
+            if let Some(width) = self.options.line_width {
+                if self.column > 0 && self.column + 2 > width {
+                    out.push(b'\n');
+                    self.column = 0;
+                }
+            }
+            self.push(b'~', &mut out);
+            self.push(b'>', &mut out);
+        }
+
+        out
+    }
+
+    fn start(&mut self, out: &mut Vec<u8>) {
+        if !self.started {
+            self.started = true;
+            if self.options.delimiters {
+                self.push(b'<', out);
+                self.push(b'~', out);
+            }
+        }
+    }
+
+    fn push(&mut self, c: u8, out: &mut Vec<u8>) {
+        out.push(c);
+        self.column += 1;
+
+        if self.options.line_width == Some(self.column) {
+            out.push(b'\n');
+            self.column = 0;
+        }
+    }
+
+    fn encode(&mut self, b: &[u8], out: &mut Vec<u8>) {
+        if self.options.z_shortcut && b == [0, 0, 0, 0] {
+            self.push(b'z', out);
+            return;
+        }
+
+        let digits = encode_group(b);
+
+        // truncate output if necessary
+        for &d in &digits[0..(b.len() + 1)] {
+            self.push(d + 33, out);
+        }
+    }
+}
+
+impl<W> Write for Ascii85Writer<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // state to restore if the inner writer fails, so that nothing is lost
+        let (group, column, started) = (self.group.clone(), self.column, self.started);
+
+        let mut out = Vec::with_capacity(buf.len() / 4 * 6 + 8);
+        self.start(&mut out);
+
+        let mut rest = buf;
+
+        // complete the pending group first
+        if !self.group.is_empty() {
+            let n = rest.len().min(4 - self.group.len());
+            self.group.extend_from_slice(&rest[..n]);
+            rest = &rest[n..];
+
+            if self.group.len() == 4 {
+                let group = std::mem::take(&mut self.group);
+                self.encode(&group, &mut out);
+            }
+        }
+
+        let mut chunks = rest.chunks_exact(4);
+        for chunk in &mut chunks {
+            self.encode(chunk, &mut out);
+        }
+        self.group.extend_from_slice(chunks.remainder());
+
+        let inner = self.inner.as_mut().expect("writer already finished");
+        if let Err(e) = inner.write_all(&out) {
+            self.group = group;
+            self.column = column;
+            self.started = started;
+            return Err(e);
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner
+            .as_mut()
+            .expect("writer already finished")
+            .flush()
+    }
+}
+
+impl<W> Drop for Ascii85Writer<W>
+where
+    W: Write,
+{
+    fn drop(&mut self) {
+        if let Some(mut inner) = self.inner.take() {
+            let out = self.end();
+            let _ = inner.write_all(&out).and_then(|_| inner.flush());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii85::{decode_ascii85_str, encode_ascii85, Ascii85Reader};
+    use std::io::Read;
+
+    fn encode(b: &[u8], options: EncodeOptions, step: usize) -> String {
+        let mut w = Ascii85Writer::with_options(vec![], options);
+        for chunk in b.chunks(step.max(1)) {
+            w.write_all(chunk).unwrap();
+        }
+        String::from_utf8(w.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_single_line() {
+        let options = EncodeOptions {
+            line_width: None,
+            ..Default::default()
+        };
+        let data = (0..=255u8).chain([0, 0, 0, 0, 0]).collect::<Vec<_>>();
+
+        for len in 0..data.len() {
+            for step in [1, 3, 4, 64] {
+                assert_eq!(
+                    encode(&data[..len], options, step),
+                    encode_ascii85(&data[..len])
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_width() {
+        let data = (0..=255u8).collect::<Vec<_>>();
+
+        for width in [2, 5, 7, 60, 76] {
+            let options = EncodeOptions {
+                line_width: Some(width),
+                ..Default::default()
+            };
+            let encoded = encode(&data, options, 7);
+
+            assert!(encoded.lines().all(|l| l.len() <= width));
+            assert_eq!(decode_ascii85_str(&encoded).unwrap(), data);
+
+            let mut decoded = vec![];
+            Ascii85Reader::new(encoded.as_bytes())
+                .read_to_end(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, data);
+        }
+    }
+
+    #[test]
+    fn test_options() {
+        let data = [0, 0, 0, 0, b'.'];
+
+        assert_eq!(encode(&data, EncodeOptions::default(), 1), "<~z/c~>");
+        assert_eq!(
+            encode(
+                &data,
+                EncodeOptions {
+                    z_shortcut: false,
+                    ..Default::default()
+                },
+                1
+            ),
+            "<~!!!!!/c~>"
+        );
+        assert_eq!(
+            encode(
+                &data,
+                EncodeOptions {
+                    delimiters: false,
+                    ..Default::default()
+                },
+                1
+            ),
+            "z/c"
+        );
+        assert_eq!(encode(&[], EncodeOptions::default(), 1), "<~~>");
+    }
+
+    #[test]
+    fn test_finish_on_drop() {
+        let mut out = vec![];
+        {
+            let mut w = Ascii85Writer::new(&mut out);
+            w.write_all(b"Man .").unwrap();
+        }
+
+        assert_eq!(out, b"<~9jqo^/c~>");
+    }
+
+    #[test]
+    fn test_failed_finish() {
+        /// Writer whose first flush fails.
+        struct FlakyFlush {
+            out: Vec<u8>,
+            failed: bool,
+        }
+
+        impl Write for FlakyFlush {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.out.write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                if self.failed {
+                    return Ok(());
+                }
+                self.failed = true;
+                Err(io::Error::other("flush failed"))
+            }
+        }
+
+        let mut inner = FlakyFlush {
+            out: vec![],
+            failed: false,
+        };
+        let mut w = Ascii85Writer::new(&mut inner);
+        w.write_all(b"Man .").unwrap();
+        assert!(w.finish().is_err());
+
+        // the end is not written again on drop
+        assert_eq!(inner.out, b"<~9jqo^/c~>");
+    }
+
+    #[test]
+    fn test_failed_write() {
+        struct FlakyWrite {
+            out: Vec<u8>,
+            failed: bool,
+        }
+
+        impl Write for FlakyWrite {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if self.failed {
+                    return self.out.write(buf);
+                }
+                self.failed = true;
+                Err(io::Error::other("write failed"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut w = Ascii85Writer::new(FlakyWrite {
+            out: vec![],
+            failed: false,
+        });
+        assert!(w.write(b"Man ").is_err());
+        w.write_all(b"Man .").unwrap();
+
+        // the failed write left no trace
+        assert_eq!(w.finish().unwrap().out, b"<~9jqo^/c~>");
+    }
+
+    #[test]
+    #[should_panic(expected = "line width must be at least 2")]
+    fn test_line_width_too_small() {
+        Ascii85Writer::with_options(
+            vec![],
+            EncodeOptions {
+                line_width: Some(1),
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_layer_file() {
+        const LAYER: &str = include_str!("../../layers/00.txt");
+        let payload = &LAYER[LAYER.find("<~").unwrap()..];
+        let data = decode_ascii85_str(payload).unwrap();
+
+        assert_eq!(
+            encode(&data, EncodeOptions::default(), 4096),
+            payload.trim_end()
+        );
+    }
+}
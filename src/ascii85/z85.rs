@@ -0,0 +1,82 @@
+//! Z85, the ZeroMQ flavour of base 85.
+//!
+//! Z85 uses a different alphabet, chosen to be safe in source code and
+//! command lines, and has neither the `z` shortcut nor any framing.
+//! Encoded data must be a multiple of 4 bytes, and encoded text a multiple
+//! of 5 characters: there are no partial groups.
+
+use super::{decode_alphabet, encode_alphabet, DecodeError, DecodeErrorKind};
+
+const ALPHABET: &[u8; 85] =
+    b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+
+pub fn decode_z85(s: &str) -> Result<Vec<u8>, DecodeError> {
+    let len = s.chars().count();
+    if !len.is_multiple_of(5) {
+        // locate the start of the incomplete group
+        let (i, _) = s.char_indices().nth(len / 5 * 5).unwrap_or_default();
+        return Err(DecodeError::at(DecodeErrorKind::TruncatedGroup, s, i));
+    }
+
+    decode_alphabet(s, ALPHABET)
+}
+
+/// Encode a buffer to Z85.
+///
+/// Returns `None` if the buffer length is not a multiple of 4, as Z85
+/// leaves padding to the caller.
+pub fn encode_z85(b: &[u8]) -> Option<String> {
+    if !b.len().is_multiple_of(4) {
+        return None;
+    }
+
+    Some(encode_alphabet(b, ALPHABET))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DECODED: &[u8] = &[0x86, 0x4F, 0xD2, 0x6F, 0xB5, 0x59, 0xF7, 0x5B];
+    const ENCODED: &str = "HelloWorld";
+
+    #[test]
+    fn test_decode_z85() {
+        assert_eq!(decode_z85(ENCODED).unwrap(), DECODED);
+        assert_eq!(decode_z85("").unwrap(), &[]);
+    }
+
+    #[test]
+    fn test_encode_z85() {
+        assert_eq!(encode_z85(DECODED).unwrap(), ENCODED);
+        assert_eq!(encode_z85(&[0, 0, 0, 0]).unwrap(), "00000");
+        assert_eq!(encode_z85(&[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(decode_z85("Hello").is_ok());
+        assert!(decode_z85("Hell").is_err());
+        assert!(decode_z85("Hell~").is_err());
+        assert!(decode_z85("<~z~>").is_err());
+
+        let err = decode_z85("HelloWor").unwrap_err();
+        assert_eq!(err.kind(), DecodeErrorKind::TruncatedGroup);
+        assert_eq!(err.position().offset, 5);
+
+        let err = decode_z85("Hello%nSc0%nSc1").unwrap_err();
+        assert_eq!(err.kind(), DecodeErrorKind::GroupOverflow);
+        assert_eq!(err.position().offset, 10);
+
+        let err = decode_z85("Hel~oWorld").unwrap_err();
+        assert_eq!(err.kind(), DecodeErrorKind::InvalidCharacter('~'));
+        assert_eq!(err.position().offset, 3);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = (0..=255u8).collect::<Vec<_>>();
+
+        assert_eq!(decode_z85(&encode_z85(&data).unwrap()).unwrap(), data);
+    }
+}
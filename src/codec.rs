@@ -0,0 +1,265 @@
+use crate::ascii85::{decode_ascii85_fast, encode_ascii85};
+use data_encoding::{Encoding, BASE32, BASE64, BASE64URL_NOPAD, HEXLOWER_PERMISSIVE};
+use std::error::Error;
+use std::sync::OnceLock;
+
+/// A text encoding for binary payloads.
+pub trait Codec {
+    fn name(&self) -> &'static str;
+    fn encode(&self, b: &[u8]) -> String;
+    fn decode(&self, s: &str) -> Result<Vec<u8>, Box<dyn Error>>;
+}
+
+/// Every known codec.
+pub const CODECS: &[&dyn Codec] = &[&Ascii85, &Base64, &Base64Url, &Base32, &Hex, &Uuencode];
+
+pub fn codec_by_name(name: &str) -> Option<&'static dyn Codec> {
+    CODECS.iter().copied().find(|c| c.name() == name)
+}
+
+/// Adobe Ascii85, as used by the onion layers.
+pub struct Ascii85;
+
+impl Codec for Ascii85 {
+    fn name(&self) -> &'static str {
+        "ascii85"
+    }
+
+    fn encode(&self, b: &[u8]) -> String {
+        encode_ascii85(b)
+    }
+
+    fn decode(&self, s: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(decode_ascii85_fast(s)?)
+    }
+}
+
+static BASE64_WS: OnceLock<Encoding> = OnceLock::new();
+static BASE64URL_WS: OnceLock<Encoding> = OnceLock::new();
+static BASE32_WS: OnceLock<Encoding> = OnceLock::new();
+static HEX_WS: OnceLock<Encoding> = OnceLock::new();
+
+/// Allow payloads to be split over several lines, building the encoding
+/// into `cache` on first use.
+fn ignore_whitespace(cache: &'static OnceLock<Encoding>, e: &Encoding) -> &'static Encoding {
+    cache.get_or_init(|| {
+        let mut spec = e.specification();
+        spec.ignore.push_str(" \t\r\n");
+        spec.encoding().expect("invalid encoding specification")
+    })
+}
+
+/// Standard Base64, with padding.
+pub struct Base64;
+
+impl Codec for Base64 {
+    fn name(&self) -> &'static str {
+        "base64"
+    }
+
+    fn encode(&self, b: &[u8]) -> String {
+        BASE64.encode(b)
+    }
+
+    fn decode(&self, s: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(ignore_whitespace(&BASE64_WS, &BASE64).decode(s.trim().as_bytes())?)
+    }
+}
+
+/// URL-safe Base64, written without padding but accepting it.
+pub struct Base64Url;
+
+impl Codec for Base64Url {
+    fn name(&self) -> &'static str {
+        "base64url"
+    }
+
+    fn encode(&self, b: &[u8]) -> String {
+        BASE64URL_NOPAD.encode(b)
+    }
+
+    fn decode(&self, s: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let s = s.trim().trim_end_matches('=');
+        Ok(ignore_whitespace(&BASE64URL_WS, &BASE64URL_NOPAD).decode(s.as_bytes())?)
+    }
+}
+
+/// RFC 4648 Base32, with padding.
+pub struct Base32;
+
+impl Codec for Base32 {
+    fn name(&self) -> &'static str {
+        "base32"
+    }
+
+    fn encode(&self, b: &[u8]) -> String {
+        BASE32.encode(b)
+    }
+
+    fn decode(&self, s: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(ignore_whitespace(&BASE32_WS, &BASE32).decode(s.trim().as_bytes())?)
+    }
+}
+
+/// Hexadecimal, written in lower case but accepting both cases.
+pub struct Hex;
+
+impl Codec for Hex {
+    fn name(&self) -> &'static str {
+        "hex"
+    }
+
+    fn encode(&self, b: &[u8]) -> String {
+        HEXLOWER_PERMISSIVE.encode(b)
+    }
+
+    fn decode(&self, s: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(ignore_whitespace(&HEX_WS, &HEXLOWER_PERMISSIVE).decode(s.trim().as_bytes())?)
+    }
+}
+
+/// Unix-to-Unix encoding, between `begin` and `end` lines.
+pub struct Uuencode;
+
+/// Number of bytes encoded per line.
+const UU_LINE_BYTES: usize = 45;
+
+fn uu_char(v: u8) -> char {
+    // use a backtick rather than a space for zero
+    if v == 0 {
+        '`'
+    } else {
+        (v + 32) as char
+    }
+}
+
+fn uu_value(c: char) -> Result<u8, Box<dyn Error>> {
+    match c {
+        ' '..='`' => Ok((c as u8 - 32) & 63),
+        c => Err(format!("Invalid uuencode character '{}'", c).into()),
+    }
+}
+
+impl Codec for Uuencode {
+    fn name(&self) -> &'static str {
+        "uuencode"
+    }
+
+    fn encode(&self, b: &[u8]) -> String {
+        let mut s = String::from("begin 644 payload\n");
+
+        for line in b.chunks(UU_LINE_BYTES) {
+            s.push(uu_char(line.len() as u8));
+            for chunk in line.chunks(3) {
+                let mut group = [0u8; 3];
+                group[..chunk.len()].copy_from_slice(chunk);
+                let n = u32::from_be_bytes([0, group[0], group[1], group[2]]);
+                for shift in [18, 12, 6, 0] {
+                    s.push(uu_char((n >> shift) as u8 & 63));
+                }
+            }
+            s.push('\n');
+        }
+        s.push_str("`\nend\n");
+
+        s
+    }
+
+    fn decode(&self, s: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut lines = s.lines().skip_while(|l| !l.starts_with("begin ")).skip(1);
+        let mut result = vec![];
+
+        loop {
+            let line = lines.next().ok_or("Missing uuencode end line")?;
+            let mut chars = line.chars();
+            let len = match chars.next() {
+                Some(c) => uu_value(c)? as usize,
+                None => continue,
+            };
+            if len == 0 {
+                break;
+            }
+
+            let vals = chars.map(uu_value).collect::<Result<Vec<_>, _>>()?;
+            if vals.len() < len.div_ceil(3) * 4 {
+                return Err(format!("Truncated uuencode line '{}'", line).into());
+            }
+
+            let bytes = vals.chunks_exact(4).flat_map(|v| {
+                let n = v.iter().fold(0u32, |n, &v| n << 6 | v as u32);
+                let [_, a, b, c] = n.to_be_bytes();
+                [a, b, c]
+            });
+            result.extend(bytes.take(len));
+        }
+
+        match lines.find(|l| !l.trim().is_empty()) {
+            Some(l) if l.trim_end() == "end" => Ok(result),
+            _ => Err("Missing uuencode end line".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_values() {
+        assert_eq!(Base64.encode(b"Man ."), "TWFuIC4=");
+        assert_eq!(Base64Url.encode(&[0xfb, 0xff]), "-_8");
+        assert_eq!(Base32.encode(b"Man ."), "JVQW4IBO");
+        assert_eq!(Hex.encode(b"Man ."), "4d616e202e");
+        assert_eq!(
+            Uuencode.encode(b"Cat"),
+            "begin 644 payload\n#0V%T\n`\nend\n"
+        );
+    }
+
+    #[test]
+    fn test_decode_multiline() {
+        assert_eq!(Base64.decode("TWFu\nIC4=\n").unwrap(), b"Man .");
+        assert_eq!(Base64Url.decode("-_8=").unwrap(), &[0xfb, 0xff]);
+        assert_eq!(Base32.decode(" JVQW\n4IBO").unwrap(), b"Man .");
+        assert_eq!(Hex.decode("4D616E\n202e").unwrap(), b"Man .");
+        assert_eq!(
+            Uuencode
+                .decode("header\nbegin 644 cat.txt\n#0V%T\n`\nend\n")
+                .unwrap(),
+            b"Cat"
+        );
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(Base64.decode("TWFu!").is_err());
+        assert!(Base32.decode("JVQW4IB").is_err());
+        assert!(Hex.decode("4d6").is_err());
+        assert!(Uuencode.decode("#0V%T\n`\nend\n").is_err());
+        assert!(Uuencode.decode("begin 644 x\n#0V\n`\nend\n").is_err());
+        assert!(Uuencode.decode("begin 644 x\n#0V%T\n").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = (0..=255u8).cycle().take(1000).collect::<Vec<_>>();
+
+        for codec in CODECS {
+            for len in [0, 1, 2, 3, 4, 5, 44, 45, 46, 90, 1000] {
+                let encoded = codec.encode(&data[..len]);
+                assert_eq!(
+                    codec.decode(&encoded).unwrap(),
+                    &data[..len],
+                    "{}",
+                    codec.name()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_codec_by_name() {
+        assert_eq!(codec_by_name("base32").unwrap().name(), "base32");
+        assert!(codec_by_name("rot13").is_none());
+    }
+}
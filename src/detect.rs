@@ -0,0 +1,277 @@
+//! Guessing the text encoding of a payload.
+//!
+//! Each candidate encoding is scored by the likelihood of the payload being
+//! random data in its alphabet: a character outside of the alphabet is
+//! treated as rare noise, and an impossible length or missing framing
+//! marker as an unlikely accident. Since a smaller alphabet gives each of
+//! its characters a higher likelihood, hex beats Base64 on text both could
+//! have written. The likelihoods are then normalized into confidences.
+
+use crate::ascii85::{decode_ascii85_fast, decode_z85};
+use crate::codec::{Base32, Base64, Base64Url, Codec, Hex};
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// Probability of a character being noise instead of part of the encoding.
+const NOISE: f64 = 0.001;
+
+/// Probability of a length forbidden by the encoding, or of missing framing.
+const ACCIDENT: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Adobe Ascii85 between `<~ ~>` delimiters.
+    Ascii85,
+    /// Adobe Ascii85 without delimiters.
+    Ascii85Unframed,
+    Z85,
+    Base64,
+    Base64Url,
+    Base32,
+    Hex,
+}
+
+pub const ENCODINGS: &[Encoding] = &[
+    Encoding::Ascii85,
+    Encoding::Ascii85Unframed,
+    Encoding::Z85,
+    Encoding::Base64,
+    Encoding::Base64Url,
+    Encoding::Base32,
+    Encoding::Hex,
+];
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Ascii85 => "ascii85",
+            Encoding::Ascii85Unframed => "ascii85-unframed",
+            Encoding::Z85 => "z85",
+            Encoding::Base64 => "base64",
+            Encoding::Base64Url => "base64url",
+            Encoding::Base32 => "base32",
+            Encoding::Hex => "hex",
+        }
+    }
+
+    /// Decode a payload with the matching decoder.
+    pub fn decode(&self, s: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        match self {
+            Encoding::Ascii85 => Ok(decode_ascii85_fast(s)?),
+            Encoding::Ascii85Unframed => Ok(decode_ascii85_fast(&format!("<~{}~>", s.trim()))?),
+            Encoding::Z85 => Ok(decode_z85(&strip_ws(s))?),
+            Encoding::Base64 => Base64.decode(s),
+            Encoding::Base64Url => Base64Url.decode(s),
+            Encoding::Base32 => Base32.decode(s),
+            Encoding::Hex => Hex.decode(s),
+        }
+    }
+
+    fn alphabet_size(&self) -> f64 {
+        match self {
+            Encoding::Ascii85 | Encoding::Ascii85Unframed | Encoding::Z85 => 85.0,
+            Encoding::Base64 | Encoding::Base64Url => 64.0,
+            Encoding::Base32 => 32.0,
+            Encoding::Hex => 16.0,
+        }
+    }
+
+    fn contains(&self, c: char) -> bool {
+        match self {
+            Encoding::Ascii85 | Encoding::Ascii85Unframed => matches!(c, '!'..='u' | 'z'),
+            Encoding::Z85 => c.is_ascii_alphanumeric() || ".-:+=^!/*?&<>()[]{}@%$#".contains(c),
+            Encoding::Base64 => c.is_ascii_alphanumeric() || c == '+' || c == '/',
+            Encoding::Base64Url => c.is_ascii_alphanumeric() || c == '-' || c == '_',
+            Encoding::Base32 => c.is_ascii_uppercase() || ('2'..='7').contains(&c),
+            Encoding::Hex => c.is_ascii_hexdigit(),
+        }
+    }
+
+    /// Whether a body of `len` characters, followed by `padding` `=`, is
+    /// possible.
+    fn valid_length(&self, len: usize, padding: usize) -> bool {
+        match self {
+            Encoding::Ascii85 | Encoding::Ascii85Unframed => len % 5 != 1 && padding == 0,
+            Encoding::Z85 => len.is_multiple_of(5) && padding == 0,
+            Encoding::Base64 | Encoding::Base64Url => match padding {
+                0 => len % 4 != 1,
+                1 | 2 => (len + padding).is_multiple_of(4),
+                _ => false,
+            },
+            Encoding::Base32 => match padding {
+                0 => matches!(len % 8, 0 | 2 | 4 | 5 | 7),
+                1 | 3 | 4 | 6 => (len + padding).is_multiple_of(8),
+                _ => false,
+            },
+            Encoding::Hex => len.is_multiple_of(2) && padding == 0,
+        }
+    }
+
+    /// Log-likelihood of a payload in this encoding.
+    fn log_likelihood(&self, s: &str) -> f64 {
+        let s = strip_ws(s);
+        let mut score = 0.0;
+
+        let (body, padding) = match self {
+            Encoding::Ascii85 => {
+                let body = s.strip_prefix("<~").unwrap_or(&s);
+                let stripped = body.strip_suffix("~>").unwrap_or(body);
+                let missing =
+                    (body.len() == s.len()) as i32 + (stripped.len() == body.len()) as i32;
+                score += missing as f64 * ACCIDENT.ln();
+                (stripped, 0)
+            }
+            Encoding::Base64 | Encoding::Base64Url | Encoding::Base32 => {
+                let body = s.trim_end_matches('=');
+                (body, s.len() - body.len())
+            }
+            _ => (s.as_str(), 0),
+        };
+
+        // `z` stands for a whole group
+        let len = match self {
+            Encoding::Ascii85 | Encoding::Ascii85Unframed => body.replace('z', "").len(),
+            _ => body.len(),
+        };
+        if !self.valid_length(len, padding) {
+            score += ACCIDENT.ln();
+        }
+
+        let hit = ((1.0 - NOISE) / self.alphabet_size()).ln();
+        let miss = (NOISE / 256.0).ln();
+        for c in body.chars() {
+            score += if self.contains(c) { hit } else { miss };
+        }
+
+        score
+    }
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+fn strip_ws(s: &str) -> String {
+    s.chars().filter(|c| !c.is_ascii_whitespace()).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate {
+    pub encoding: Encoding,
+    /// Probability of this encoding among the candidates, from 0 to 1.
+    pub confidence: f64,
+}
+
+/// Rank the likely encodings of a payload, most likely first.
+pub fn detect_encoding(s: &str) -> Vec<Candidate> {
+    let scores = ENCODINGS
+        .iter()
+        .map(|e| e.log_likelihood(s))
+        .collect::<Vec<_>>();
+
+    // normalize in the log domain, likelihoods of long payloads underflow
+    let max = scores.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let weights = scores.iter().map(|s| (s - max).exp()).collect::<Vec<_>>();
+    let total = weights.iter().sum::<f64>();
+
+    let mut candidates = ENCODINGS
+        .iter()
+        .zip(weights)
+        .map(|(&encoding, w)| Candidate {
+            encoding,
+            confidence: w / total,
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii85::{encode_ascii85, encode_z85};
+
+    fn best(s: &str) -> Candidate {
+        detect_encoding(s)[0]
+    }
+
+    fn confidence(s: &str, encoding: Encoding) -> f64 {
+        detect_encoding(s)
+            .into_iter()
+            .find(|c| c.encoding == encoding)
+            .unwrap()
+            .confidence
+    }
+
+    fn data() -> Vec<u8> {
+        (0..=255u8).cycle().skip(7).step_by(13).take(400).collect()
+    }
+
+    #[test]
+    fn test_detect_encoded_data() {
+        let data = data();
+        let encoded = [
+            (Encoding::Ascii85, encode_ascii85(&data)),
+            (
+                Encoding::Ascii85Unframed,
+                encode_ascii85(&data)[2..]
+                    .trim_end_matches("~>")
+                    .to_string(),
+            ),
+            (Encoding::Z85, encode_z85(&data).unwrap()),
+            (Encoding::Base64, Base64.encode(&data)),
+            (Encoding::Base64Url, Base64Url.encode(&data)),
+            (Encoding::Base32, Base32.encode(&data)),
+            (Encoding::Hex, Hex.encode(&data)),
+        ];
+
+        for (encoding, s) in encoded {
+            let candidate = best(&s);
+            assert_eq!(candidate.encoding, encoding);
+            assert!(candidate.confidence > 0.99, "{:?}", candidate);
+            assert_eq!(candidate.encoding.decode(&s).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_detect_layer_payload() {
+        const LAYER: &str = include_str!("../layers/00.txt");
+        let payload = &LAYER[LAYER.find("<~").unwrap()..];
+
+        assert_eq!(best(payload).encoding, Encoding::Ascii85);
+    }
+
+    #[test]
+    fn test_smaller_alphabet_wins() {
+        let candidates = detect_encoding("deadbeef");
+
+        assert_eq!(candidates[0].encoding, Encoding::Hex);
+        assert!(candidates[1].confidence > 0.0);
+    }
+
+    #[test]
+    fn test_length_constraints() {
+        // Z85 has no partial groups, unlike Ascii85
+        let ratio = |s| confidence(s, Encoding::Z85) / confidence(s, Encoding::Ascii85Unframed);
+        assert!((ratio("HelloWorld") - 1.0).abs() < 1e-9);
+        assert!(ratio("HelloWorl") < 0.1);
+        // Base64 padding makes the length a multiple of 4
+        assert_eq!(best("TWFuIC4=").encoding, Encoding::Base64);
+    }
+
+    #[test]
+    fn test_confidences() {
+        let candidates = detect_encoding("<~9jqo^/c~>");
+        let total = candidates.iter().map(|c| c.confidence).sum::<f64>();
+
+        assert!((total - 1.0).abs() < 1e-9);
+        assert!(candidates
+            .windows(2)
+            .all(|w| w[0].confidence >= w[1].confidence));
+        assert_eq!(candidates[0].encoding, Encoding::Ascii85);
+    }
+}
@@ -0,0 +1,237 @@
+//! Traces of layer transforms: their statistics, and their intermediate
+//! buffers, dumped for debugging.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A named buffer produced while solving a layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stage {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Value of a statistic recorded while solving a layer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum Stat {
+    Count(u64),
+    Text(String),
+    /// Counts by name, such as the number of packets rejected for each reason.
+    Counts(BTreeMap<&'static str, u64>),
+}
+
+impl From<u64> for Stat {
+    fn from(n: u64) -> Self {
+        Self::Count(n)
+    }
+}
+
+impl From<usize> for Stat {
+    fn from(n: usize) -> Self {
+        Self::Count(n as u64)
+    }
+}
+
+impl From<&str> for Stat {
+    fn from(s: &str) -> Self {
+        Self::Text(s.to_string())
+    }
+}
+
+impl From<String> for Stat {
+    fn from(s: String) -> Self {
+        Self::Text(s)
+    }
+}
+
+impl From<BTreeMap<&'static str, u64>> for Stat {
+    fn from(counts: BTreeMap<&'static str, u64>) -> Self {
+        Self::Counts(counts)
+    }
+}
+
+/// Stages of a layer transform, in order, and its statistics. Buffers are
+/// only copied when the trace is recording, so layers can trace
+/// unconditionally.
+#[derive(Debug, Default)]
+pub struct Trace {
+    recording: bool,
+    stages: Vec<Stage>,
+    stats: BTreeMap<String, Stat>,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn recording() -> Self {
+        Self {
+            recording: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn stage(&mut self, name: impl Into<String>, data: &[u8]) {
+        if self.recording {
+            self.stages.push(Stage {
+                name: name.into(),
+                data: data.to_vec(),
+            });
+        }
+    }
+
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    /// Record a statistic, replacing any previous value of the same name.
+    pub fn stat(&mut self, name: &str, value: impl Into<Stat>) {
+        self.stats.insert(name.to_string(), value.into());
+    }
+
+    pub fn stats(&self) -> &BTreeMap<String, Stat> {
+        &self.stats
+    }
+}
+
+/// Format a buffer as `hexdump -C` does, without collapsing repeated lines.
+pub fn hex_dump(b: &[u8]) -> String {
+    let mut s = String::new();
+
+    for (i, line) in b.chunks(16).enumerate() {
+        write!(s, "{:08x} ", i * 16).unwrap();
+        for j in 0..16 {
+            if j % 8 == 0 {
+                s.push(' ');
+            }
+            match line.get(j) {
+                Some(c) => write!(s, "{:02x} ", c).unwrap(),
+                None => s.push_str("   "),
+            }
+        }
+        let text = line
+            .iter()
+            .map(|&c| match c {
+                b' '..=b'~' => c as char,
+                _ => '.',
+            })
+            .collect::<String>();
+        writeln!(s, " |{}|", text).unwrap();
+    }
+    writeln!(s, "{:08x}", b.len()).unwrap();
+
+    s
+}
+
+/// Write the stages of a trace to `dir`, each one as a binary file and a hex
+/// dump sidecar, numbered in order, as in `02-key.bin` and `02-key.hex`.
+/// Numbers are padded to the same width, so that file names sort in order.
+pub fn write_dump(dir: impl AsRef<Path>, trace: &Trace) -> io::Result<()> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+
+    let width = trace.stages().len().to_string().len().max(2);
+    for (i, stage) in trace.stages().iter().enumerate() {
+        let stem = format!("{:0width$}-{}", i, stage.name);
+        fs::write(dir.join(format!("{}.bin", stem)), &stage.data)?;
+        fs::write(dir.join(format!("{}.hex", stem)), hex_dump(&stage.data))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_dump() {
+        let dump = hex_dump(b"==[ Layer 0/6: ASCII85 ]\n\x00\xff");
+
+        assert_eq!(
+            dump,
+            "\
+00000000  3d 3d 5b 20 4c 61 79 65  72 20 30 2f 36 3a 20 41  |==[ Layer 0/6: A|
+00000010  53 43 49 49 38 35 20 5d  0a 00 ff                 |SCII85 ]...|
+0000001b
+"
+        );
+        assert_eq!(hex_dump(&[]), "00000000\n");
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut trace = Trace::new();
+        trace.stage("payload", b"abc");
+        trace.stat("bytes_dropped", 2usize);
+        assert!(trace.stages().is_empty());
+        assert_eq!(trace.stats()["bytes_dropped"], Stat::Count(2));
+
+        let mut trace = Trace::recording();
+        trace.stage("payload", b"abc");
+        trace.stage(format!("datagram-{}", 1), b"d");
+        assert_eq!(
+            trace.stages(),
+            [
+                Stage {
+                    name: String::from("payload"),
+                    data: b"abc".to_vec()
+                },
+                Stage {
+                    name: String::from("datagram-1"),
+                    data: b"d".to_vec()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_dump() {
+        let dir = std::env::temp_dir().join(format!("data-onion-dump-{}", std::process::id()));
+        let mut trace = Trace::recording();
+        trace.stage("payload", b"abc");
+        trace.stage("output", b"def");
+
+        write_dump(&dir, &trace).unwrap();
+        let read = |name: &str| fs::read(dir.join(name)).unwrap();
+        let files = (
+            read("00-payload.bin"),
+            read("01-output.bin"),
+            read("01-output.hex"),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(files.0, b"abc");
+        assert_eq!(files.1, b"def");
+        assert_eq!(files.2, hex_dump(b"def").into_bytes());
+    }
+
+    #[test]
+    fn test_write_dump_sorted() {
+        let dir = std::env::temp_dir().join(format!("data-onion-sorted-{}", std::process::id()));
+        let mut trace = Trace::recording();
+        for i in 0..120 {
+            trace.stage(format!("stage-{}", i), &[i]);
+        }
+
+        write_dump(&dir, &trace).unwrap();
+        let mut names = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.ends_with(".bin"))
+            .collect::<Vec<_>>();
+        fs::remove_dir_all(&dir).unwrap();
+
+        names.sort();
+        assert_eq!(names.len(), 120);
+        assert_eq!(names[0], "000-stage-0.bin");
+        assert_eq!(names[99], "099-stage-99.bin");
+        assert_eq!(names[119], "119-stage-119.bin");
+    }
+}
@@ -0,0 +1,189 @@
+use crate::layer2::ParityError;
+use crate::layer3::XorKeyError;
+use crate::layer4::PacketError;
+use crate::layer5::KeyUnwrapError;
+use crate::layer6::VmError;
+use crate::layer_file::LayerFileError;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::io;
+
+/// Failure while peeling, with the number of the layer at fault when known.
+#[derive(Debug)]
+pub enum PeelError {
+    /// Reading or writing a layer file.
+    Io {
+        layer: Option<u8>,
+        source: io::Error,
+    },
+    /// A layer file without a valid header or payload.
+    Payload {
+        layer: Option<u8>,
+        source: LayerFileError,
+    },
+    /// A layer output which does not look like the next layer.
+    Output {
+        layer: u8,
+        source: LayerFileError,
+    },
+    /// A layer no registered solver handles.
+    UnknownLayer {
+        layer: u8,
+        title: String,
+    },
+    /// A payload which cannot be decoded.
+    Encoding {
+        layer: Option<u8>,
+        source: Box<dyn Error>,
+    },
+    Parity {
+        layer: u8,
+        source: ParityError,
+    },
+    XorKey {
+        layer: u8,
+        source: XorKeyError,
+    },
+    Packet {
+        layer: u8,
+        source: PacketError,
+    },
+    KeyUnwrap {
+        layer: u8,
+        source: KeyUnwrapError,
+    },
+    Vm {
+        layer: u8,
+        source: VmError,
+    },
+    /// Failure of a layer defined outside of this crate.
+    Other {
+        layer: u8,
+        source: Box<dyn Error>,
+    },
+}
+
+impl PeelError {
+    pub fn layer(&self) -> Option<u8> {
+        match self {
+            PeelError::Io { layer, .. }
+            | PeelError::Payload { layer, .. }
+            | PeelError::Encoding { layer, .. } => *layer,
+            PeelError::Output { layer, .. }
+            | PeelError::UnknownLayer { layer, .. }
+            | PeelError::Parity { layer, .. }
+            | PeelError::XorKey { layer, .. }
+            | PeelError::Packet { layer, .. }
+            | PeelError::KeyUnwrap { layer, .. }
+            | PeelError::Vm { layer, .. }
+            | PeelError::Other { layer, .. } => Some(*layer),
+        }
+    }
+
+    /// Attribute the error to layer `n`, as numbered in its layer file.
+    pub fn at_layer(mut self, n: u8) -> Self {
+        match &mut self {
+            PeelError::Io { layer, .. }
+            | PeelError::Payload { layer, .. }
+            | PeelError::Encoding { layer, .. } => *layer = Some(n),
+            PeelError::Output { layer, .. }
+            | PeelError::UnknownLayer { layer, .. }
+            | PeelError::Parity { layer, .. }
+            | PeelError::XorKey { layer, .. }
+            | PeelError::Packet { layer, .. }
+            | PeelError::KeyUnwrap { layer, .. }
+            | PeelError::Vm { layer, .. }
+            | PeelError::Other { layer, .. } => *layer = n,
+        }
+        self
+    }
+}
+
+impl Display for PeelError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(layer) = self.layer() {
+            write!(f, "layer {}: ", layer)?;
+        }
+
+        match self {
+            PeelError::Io { source, .. } => write!(f, "{}", source),
+            PeelError::Payload { source, .. } => write!(f, "{}", source),
+            PeelError::Output { source, .. } => write!(f, "invalid output, {}", source),
+            PeelError::UnknownLayer { title, .. } => write!(f, "no solver for \"{}\"", title),
+            PeelError::Encoding { source, .. } => write!(f, "invalid payload, {}", source),
+            PeelError::Parity { source, .. } => write!(f, "{}", source),
+            PeelError::XorKey { source, .. } => write!(f, "{}", source),
+            PeelError::Packet { source, .. } => write!(f, "{}", source),
+            PeelError::KeyUnwrap { source, .. } => write!(f, "{}", source),
+            PeelError::Vm { source, .. } => write!(f, "{}", source),
+            PeelError::Other { source, .. } => write!(f, "{}", source),
+        }
+    }
+}
+
+impl Error for PeelError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PeelError::Io { source, .. } => Some(source),
+            PeelError::Payload { source, .. } => Some(source),
+            PeelError::Output { source, .. } => Some(source),
+            PeelError::UnknownLayer { .. } => None,
+            PeelError::Encoding { source, .. } => Some(source.as_ref()),
+            PeelError::Parity { source, .. } => Some(source),
+            PeelError::XorKey { source, .. } => Some(source),
+            PeelError::Packet { source, .. } => Some(source),
+            PeelError::KeyUnwrap { source, .. } => Some(source),
+            PeelError::Vm { source, .. } => Some(source),
+            PeelError::Other { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
+
+impl From<io::Error> for PeelError {
+    fn from(e: io::Error) -> Self {
+        PeelError::Io {
+            layer: None,
+            source: e,
+        }
+    }
+}
+
+impl From<LayerFileError> for PeelError {
+    fn from(e: LayerFileError) -> Self {
+        PeelError::Payload {
+            layer: None,
+            source: e,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let e = PeelError::XorKey {
+            layer: 3,
+            source: XorKeyError,
+        };
+        assert_eq!(e.to_string(), format!("layer 3: {}", XorKeyError));
+        assert_eq!(e.layer(), Some(3));
+
+        let e = PeelError::from(LayerFileError::MissingHeader);
+        assert_eq!(e.to_string(), "missing layer header");
+        assert_eq!(e.layer(), None);
+    }
+
+    #[test]
+    fn test_source() {
+        let e = PeelError::Vm {
+            layer: 6,
+            source: VmError::InvalidOpcode { pc: 0, opcode: 0 },
+        };
+        let source = e.source().unwrap().downcast_ref::<VmError>();
+
+        assert_eq!(source, Some(&VmError::InvalidOpcode { pc: 0, opcode: 0 }));
+    }
+}
@@ -0,0 +1,177 @@
+//! Layers of an onion, and the registry the pipeline walks through.
+
+use crate::codec::{Ascii85, Codec};
+use crate::dump::Trace;
+use crate::error::PeelError;
+use crate::layer_file::LayerFile;
+use crate::{layer0, layer1, layer2, layer3, layer4, layer5, layer6};
+
+/// A layer transform, turning a decoded payload into the next layer.
+pub trait Layer {
+    /// Number of the layer in its onion, from 0.
+    fn number(&self) -> u8;
+
+    /// Title of the layer, as found in its header.
+    fn name(&self) -> &'static str;
+
+    /// Short summary of the transform.
+    fn description(&self) -> &'static str;
+
+    /// Encoding of the payload, Ascii85 for every layer of Tom's onion.
+    fn codec(&self) -> &dyn Codec {
+        &Ascii85
+    }
+
+    fn decode(&self, payload: Vec<u8>) -> Result<Vec<u8>, PeelError>;
+
+    /// Decode a payload, recording the intermediate buffers worth
+    /// inspecting to `trace`.
+    fn decode_traced(&self, payload: Vec<u8>, trace: &mut Trace) -> Result<Vec<u8>, PeelError> {
+        let _ = trace;
+        self.decode(payload)
+    }
+}
+
+/// Layers of an onion, ordered by number.
+#[derive(Default)]
+pub struct Registry {
+    layers: Vec<Box<dyn Layer>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The layers of Tom's Data Onion.
+    pub fn standard() -> Self {
+        let mut registry = Self::new();
+        registry
+            .register(layer0::AsciiLayer)
+            .register(layer1::BitwiseLayer)
+            .register(layer2::ParityLayer)
+            .register(layer3::XorLayer)
+            .register(layer4::NetworkLayer)
+            .register(layer5::AesLayer)
+            .register(layer6::VmLayer);
+        registry
+    }
+
+    /// Add a layer, replacing any other one with the same number.
+    pub fn register(&mut self, layer: impl Layer + 'static) -> &mut Self {
+        match self
+            .layers
+            .binary_search_by_key(&layer.number(), |l| l.number())
+        {
+            Ok(i) => self.layers[i] = Box::new(layer),
+            Err(i) => self.layers.insert(i, Box::new(layer)),
+        }
+        self
+    }
+
+    pub fn get(&self, number: u8) -> Option<&dyn Layer> {
+        self.layers
+            .binary_search_by_key(&number, |l| l.number())
+            .ok()
+            .map(|i| self.layers[i].as_ref())
+    }
+
+    /// Find the layer solving a file, by title, or by number if no title
+    /// matches.
+    pub fn resolve(&self, file: &LayerFile) -> Option<&dyn Layer> {
+        self.iter()
+            .find(|l| l.name().eq_ignore_ascii_case(file.title))
+            .or_else(|| self.get(file.number))
+    }
+
+    /// Iterate over the layers, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Layer> {
+        self.layers.iter().map(|l| l.as_ref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Reverse(u8);
+
+    impl Layer for Reverse {
+        fn number(&self) -> u8 {
+            self.0
+        }
+
+        fn name(&self) -> &'static str {
+            "Reverse"
+        }
+
+        fn description(&self) -> &'static str {
+            "Reverse the payload."
+        }
+
+        fn decode(&self, mut payload: Vec<u8>) -> Result<Vec<u8>, PeelError> {
+            payload.reverse();
+            Ok(payload)
+        }
+    }
+
+    #[test]
+    fn test_standard() {
+        let registry = Registry::standard();
+        let numbers = registry.iter().map(|l| l.number()).collect::<Vec<_>>();
+
+        assert_eq!(numbers, (0..=6).collect::<Vec<_>>());
+        assert_eq!(registry.get(3).unwrap().name(), "XOR Encryption");
+        assert!(registry.get(7).is_none());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let registry = Registry::standard();
+        let file = |number, title| LayerFile {
+            number,
+            total: 6,
+            title,
+            instructions: "",
+            payload: "",
+        };
+
+        // the title wins over the number, for reordered onions
+        let layer = registry.resolve(&file(1, "Parity Bit")).unwrap();
+        assert_eq!(layer.number(), 2);
+        let layer = registry.resolve(&file(4, "Unknown")).unwrap();
+        assert_eq!(layer.name(), "Network Traffic");
+        assert!(registry.resolve(&file(9, "Unknown")).is_none());
+    }
+
+    #[test]
+    fn test_register_in_order() {
+        let mut registry = Registry::new();
+        registry.register(Reverse(2)).register(Reverse(0));
+        registry.register(layer1::BitwiseLayer);
+
+        let numbers = registry.iter().map(|l| l.number()).collect::<Vec<_>>();
+        assert_eq!(numbers, [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_register_replaces() {
+        let mut registry = Registry::standard();
+        registry.register(Reverse(3));
+
+        assert_eq!(registry.len(), 7);
+        assert_eq!(registry.get(3).unwrap().name(), "Reverse");
+        assert_eq!(
+            registry.get(3).unwrap().decode(vec![1, 2, 3]).unwrap(),
+            [3, 2, 1]
+        );
+    }
+}
@@ -0,0 +1,23 @@
+use crate::error::PeelError;
+use crate::layer::Layer;
+
+/// The first layer only needs its payload decoded.
+pub struct AsciiLayer;
+
+impl Layer for AsciiLayer {
+    fn number(&self) -> u8 {
+        0
+    }
+
+    fn name(&self) -> &'static str {
+        "ASCII85"
+    }
+
+    fn description(&self) -> &'static str {
+        "Ascii85-encoded payload, without any further transform."
+    }
+
+    fn decode(&self, payload: Vec<u8>) -> Result<Vec<u8>, PeelError> {
+        Ok(payload)
+    }
+}
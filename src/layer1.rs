@@ -1,3 +1,6 @@
+use crate::error::PeelError;
+use crate::layer::Layer;
+
 pub fn flip_and_rotate(b: u8) -> u8 {
     let mask = 0b01010101u8;
     let flipped = b ^ mask;
@@ -5,6 +8,26 @@ pub fn flip_and_rotate(b: u8) -> u8 {
     (flipped >> 1) | (lsb << 7)
 }
 
+pub struct BitwiseLayer;
+
+impl Layer for BitwiseLayer {
+    fn number(&self) -> u8 {
+        1
+    }
+
+    fn name(&self) -> &'static str {
+        "Bitwise Operations"
+    }
+
+    fn description(&self) -> &'static str {
+        "Flip every other bit, then rotate each byte right."
+    }
+
+    fn decode(&self, payload: Vec<u8>) -> Result<Vec<u8>, PeelError> {
+        Ok(payload.into_iter().map(flip_and_rotate).collect())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
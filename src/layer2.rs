@@ -1,4 +1,27 @@
+use crate::dump::Trace;
+use crate::error::PeelError;
+use crate::layer::Layer;
 use bitvec::prelude::*;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// Data bits left over once the bytes with a wrong parity bit are dropped.
+///
+/// Each valid byte carries 7 bits, so a sound payload keeps a multiple of 8
+/// of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParityError {
+    pub bits: usize,
+}
+
+impl Display for ParityError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} data bits left over after parity check", self.bits)
+    }
+}
+
+impl Error for ParityError {}
 
 fn correct_byte(b: u8) -> Option<u8> {
     let parity_bit = b & 1;
@@ -11,7 +34,7 @@ fn correct_byte(b: u8) -> Option<u8> {
     }
 }
 
-pub fn parse_parity_buffer(b: &[u8]) -> Vec<u8> {
+pub fn parse_parity_buffer(b: &[u8]) -> Result<Vec<u8>, ParityError> {
     let mut bb = bitvec![u8, Msb0;];
     for &byte in b {
         if let Some(mut ab) = correct_byte(byte) {
@@ -23,7 +46,42 @@ pub fn parse_parity_buffer(b: &[u8]) -> Vec<u8> {
         }
     }
 
-    bb.into_vec()
+    let bits = bb.len() % 8;
+    if bits != 0 {
+        return Err(ParityError { bits });
+    }
+
+    Ok(bb.into_vec())
+}
+
+pub struct ParityLayer;
+
+impl Layer for ParityLayer {
+    fn number(&self) -> u8 {
+        2
+    }
+
+    fn name(&self) -> &'static str {
+        "Parity Bit"
+    }
+
+    fn description(&self) -> &'static str {
+        "Drop bytes with a wrong parity bit, then pack the 7 data bits."
+    }
+
+    fn decode(&self, payload: Vec<u8>) -> Result<Vec<u8>, PeelError> {
+        self.decode_traced(payload, &mut Trace::new())
+    }
+
+    fn decode_traced(&self, payload: Vec<u8>, trace: &mut Trace) -> Result<Vec<u8>, PeelError> {
+        let dropped = payload.iter().filter(|&&b| correct_byte(b).is_none());
+        trace.stat("bytes_dropped", dropped.count());
+
+        parse_parity_buffer(&payload).map_err(|source| PeelError::Parity {
+            layer: self.number(),
+            source,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -58,6 +116,15 @@ mod tests {
             0b00111000u8,
             0b01111000u8,
         ];
-        assert_eq!(parse_parity_buffer(b), out);
+        assert_eq!(parse_parity_buffer(b).unwrap(), out);
+    }
+
+    #[test]
+    fn test_left_over_bits() {
+        // two bytes with a wrong parity bit, leaving 42 bits
+        let b = &[0b10000001u8, 0b11000001, 0b11100001, 0b11110000].repeat(2);
+
+        assert_eq!(parse_parity_buffer(b), Err(ParityError { bits: 2 }));
+        assert_eq!(parse_parity_buffer(&b[..0]), Ok(vec![]));
     }
 }
@@ -1,3 +1,31 @@
+use crate::dump::Trace;
+use crate::error::PeelError;
+use crate::layer::Layer;
+use data_encoding::HEXLOWER;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// The known plaintext, needed to recover the key, cannot be found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XorKeyError;
+
+impl Display for XorKeyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "no repeated sequence to recover the XOR key from")
+    }
+}
+
+impl Error for XorKeyError {}
+
+/// Decode a payload with the key recovered by `find_xor_key`.
+pub fn decode_xor_encoded_payload(b: &[u8]) -> Result<Vec<u8>, XorKeyError> {
+    let key = find_xor_key(b)?;
+
+    // decode the whole sequence
+    Ok(xor(&key, b))
+}
+
 /// XOR encryption is very weak against repeating sequences.
 /// At some point, there will be the sequence:
 ///
@@ -9,11 +37,10 @@
 /// by exactly the 15 first ones.
 /// Once we have located this sequence, as we know the 15 first bytes already,
 /// we just have to find these bytes and sync the key.
-/// Then we can decode the whole thing.
 /// Alternatively, just sync with the absolute position in the payload.
-pub fn decode_xor_encoded_payload(b: &[u8]) -> Option<Vec<u8>> {
+pub fn find_xor_key(b: &[u8]) -> Result<Vec<u8>, XorKeyError> {
     // find the paylaod marker sequence
-    let i = find_repeated_sequence(b)?;
+    let i = find_repeated_sequence(b).ok_or(XorKeyError)?;
     let sub = &b[i..i + 32];
 
     // sync our stream to the next key start
@@ -32,10 +59,7 @@ pub fn decode_xor_encoded_payload(b: &[u8]) -> Option<Vec<u8>> {
     };
 
     // decode the key
-    let key = get_key_from_equal_bytes(&seq);
-
-    // decode the whole sequence
-    Some(xor(&key, b))
+    Ok(get_key_from_equal_bytes(&seq))
 }
 
 // decode sequence of "=" bytes
@@ -56,7 +80,7 @@ fn xor(key: &[u8], b: &[u8]) -> Vec<u8> {
 
 fn find_repeated_sequence(b: &[u8]) -> Option<usize> {
     let mut i = 0;
-    let start = b[0];
+    let start = *b.first()?;
     loop {
         // read 32 bytes
         let seq = b.get(i..i + 32);
@@ -75,6 +99,37 @@ fn find_repeated_sequence(b: &[u8]) -> Option<usize> {
     }
 }
 
+pub struct XorLayer;
+
+impl Layer for XorLayer {
+    fn number(&self) -> u8 {
+        3
+    }
+
+    fn name(&self) -> &'static str {
+        "XOR Encryption"
+    }
+
+    fn description(&self) -> &'static str {
+        "Recover the 32-byte repeating XOR key from known plaintext."
+    }
+
+    fn decode(&self, payload: Vec<u8>) -> Result<Vec<u8>, PeelError> {
+        self.decode_traced(payload, &mut Trace::new())
+    }
+
+    fn decode_traced(&self, payload: Vec<u8>, trace: &mut Trace) -> Result<Vec<u8>, PeelError> {
+        let key = find_xor_key(&payload).map_err(|source| PeelError::XorKey {
+            layer: self.number(),
+            source,
+        })?;
+        trace.stage("key", &key);
+        trace.stat("key", HEXLOWER.encode(&key));
+
+        Ok(xor(&key, &payload))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -87,4 +142,10 @@ mod tests {
 
         assert_eq!(xor(k, v), expected);
     }
+
+    #[test]
+    fn test_key_not_found() {
+        assert_eq!(decode_xor_encoded_payload(&[]), Err(XorKeyError));
+        assert_eq!(decode_xor_encoded_payload(&[0; 40]), Err(XorKeyError));
+    }
 }
@@ -1,10 +1,73 @@
+use crate::dump::Trace;
+use crate::error::PeelError;
+use crate::layer::Layer;
 use packet::ip::Protocol;
 use packet::{ip, udp, Packet};
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
 use std::io::Seek;
 use std::net::Ipv4Addr;
 
-pub fn parse_ip_payload(b: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+/// An IPv4 packet whose UDP datagram cannot be parsed.
+#[derive(Debug)]
+pub struct PacketError {
+    /// Offset of the IPv4 packet in the payload.
+    pub offset: usize,
+    pub source: packet::Error,
+}
+
+impl Display for PacketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid packet at offset {}: {}",
+            self.offset, self.source
+        )
+    }
+}
+
+impl Error for PacketError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Reason for ignoring a packet, the first check it fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rejection {
+    IpChecksum,
+    Source,
+    Destination,
+    UdpChecksum,
+    Port,
+}
+
+impl Rejection {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rejection::IpChecksum => "ip_checksum",
+            Rejection::Source => "source",
+            Rejection::Destination => "destination",
+            Rejection::UdpChecksum => "udp_checksum",
+            Rejection::Port => "port",
+        }
+    }
+}
+
+pub fn parse_ip_payload(b: &[u8]) -> Result<Vec<u8>, PacketError> {
+    Ok(filter_datagrams(b)?.concat())
+}
+
+/// Get the payloads of the valid UDP datagrams, in order.
+pub fn filter_datagrams(b: &[u8]) -> Result<Vec<Vec<u8>>, PacketError> {
+    Ok(check_packets(b)?.into_iter().flatten().collect())
+}
+
+/// Check every packet in order, getting the payload of its UDP datagram
+/// or the reason for ignoring it.
+pub fn check_packets(b: &[u8]) -> Result<Vec<Result<Vec<u8>, Rejection>>, PacketError> {
     let mut stream = b;
     let mut result = vec![];
 
@@ -12,39 +75,43 @@ pub fn parse_ip_payload(b: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
         // verify checksum
         let ck = ipv4_packet.checksum();
         let actual_ck = ip::v4::checksum(&stream[..20]);
-        let mut ignore = ck != actual_ck;
+        let mut rejection = (ck != actual_ck).then_some(Rejection::IpChecksum);
 
         // source ip
         let source = ipv4_packet.source();
         let authorized = Ipv4Addr::new(10, 1, 1, 10);
         if source != authorized {
-            ignore = true;
+            rejection = rejection.or(Some(Rejection::Source));
         }
 
         // destination
         let dest = ipv4_packet.destination();
         let authorized = Ipv4Addr::new(10, 1, 1, 200);
         if dest != authorized {
-            ignore = true;
+            rejection = rejection.or(Some(Rejection::Destination));
         }
 
-        let udp_packet = udp::Packet::new(ipv4_packet.payload())?;
+        let udp_packet = udp::Packet::new(ipv4_packet.payload()).map_err(|source| PacketError {
+            offset: b.len() - stream.len(),
+            source,
+        })?;
 
         // verify checksum
         let ck = udp_packet.checksum();
         let actual_ck = checksum(&ip::Packet::V4(ipv4_packet), ipv4_packet.payload());
         if ck != actual_ck {
-            ignore = true;
+            rejection = rejection.or(Some(Rejection::UdpChecksum));
         }
 
         // port verification
         if udp_packet.destination() != 42069 {
-            ignore = true;
+            rejection = rejection.or(Some(Rejection::Port));
         }
 
-        if !ignore {
-            result.extend_from_slice(udp_packet.payload());
-        }
+        result.push(match rejection {
+            Some(rejection) => Err(rejection),
+            None => Ok(udp_packet.payload().to_vec()),
+        });
 
         stream = &stream[ipv4_packet.length() as usize..];
     }
@@ -124,3 +191,47 @@ fn checksum<B: AsRef<[u8]>>(ip: &ip::Packet<B>, buffer: &[u8]) -> u16 {
 
     !result as u16
 }
+
+pub struct NetworkLayer;
+
+impl Layer for NetworkLayer {
+    fn number(&self) -> u8 {
+        4
+    }
+
+    fn name(&self) -> &'static str {
+        "Network Traffic"
+    }
+
+    fn description(&self) -> &'static str {
+        "Keep the valid UDP datagrams between two hosts."
+    }
+
+    fn decode(&self, payload: Vec<u8>) -> Result<Vec<u8>, PeelError> {
+        self.decode_traced(payload, &mut Trace::new())
+    }
+
+    fn decode_traced(&self, payload: Vec<u8>, trace: &mut Trace) -> Result<Vec<u8>, PeelError> {
+        let packets = check_packets(&payload).map_err(|source| PeelError::Packet {
+            layer: self.number(),
+            source,
+        })?;
+
+        let mut rejected = BTreeMap::new();
+        let mut datagrams = vec![];
+        for packet in packets {
+            match packet {
+                Ok(datagram) => datagrams.push(datagram),
+                Err(rejection) => *rejected.entry(rejection.name()).or_insert(0) += 1,
+            }
+        }
+        let width = datagrams.len().to_string().len();
+        for (i, datagram) in datagrams.iter().enumerate() {
+            trace.stage(format!("datagram-{:0width$}", i), datagram);
+        }
+        trace.stat("packets_accepted", datagrams.len());
+        trace.stat("packets_rejected", rejected);
+
+        Ok(datagrams.concat())
+    }
+}
@@ -1,22 +1,64 @@
+use crate::dump::Trace;
+use crate::error::PeelError;
+use crate::layer::Layer;
 use aes::cipher::generic_array::GenericArray;
 use aes::cipher::{block_padding::NoPadding, BlockDecryptMut, KeyInit, KeyIvInit, StreamCipher};
 use aes::Aes256;
-use byteorder::{BigEndian, ReadBytesExt};
-use nom::lib::std::iter::repeat_with;
 use std::error::Error;
-use std::io::Cursor;
+use std::fmt;
+use std::fmt::{Display, Formatter};
 
-pub fn decode_aes_payload(b: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+/// Length of the key material before the encrypted payload.
+const HEADER_LEN: usize = 96;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyUnwrapError {
+    /// A payload too short to hold the key material, with its length.
+    Truncated(usize),
+    /// An unwrapped key which fails the integrity check of RFC 3394, as
+    /// with a wrong key encrypting key.
+    Integrity,
+    /// A payload longer than the counter mode keystream.
+    KeystreamOverflow,
+}
+
+impl Display for KeyUnwrapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyUnwrapError::Truncated(len) => {
+                write!(f, "payload of {} bytes too short for key material", len)
+            }
+            KeyUnwrapError::Integrity => write!(f, "unwrapped key fails integrity check"),
+            KeyUnwrapError::KeystreamOverflow => write!(f, "payload exceeds keystream"),
+        }
+    }
+}
+
+impl Error for KeyUnwrapError {}
+
+pub fn decode_aes_payload(b: &[u8]) -> Result<Vec<u8>, KeyUnwrapError> {
+    decode_aes_payload_traced(b, &mut Trace::new())
+}
+
+/// Decode a payload as `decode_aes_payload`, recording the unwrapped key and
+/// the IV.
+pub fn decode_aes_payload_traced(b: &[u8], trace: &mut Trace) -> Result<Vec<u8>, KeyUnwrapError> {
     type Aes256Ctr = ctr::Ctr128BE<Aes256>;
 
+    if b.len() < HEADER_LEN {
+        return Err(KeyUnwrapError::Truncated(b.len()));
+    }
+
     let kek = &b[0..32];
-    let _wkiv = &b[32..40];
+    let wkiv = &b[32..40];
     let wrapped_key = &b[40..80];
     let iv = &b[80..96];
-    let payload = &b[96..];
+    let payload = &b[HEADER_LEN..];
 
     // decrypt the key
-    let decrypted_key = unwrap_key(kek, wrapped_key)?;
+    let decrypted_key = unwrap_key(kek, wkiv, wrapped_key)?;
+    trace.stage("key", &decrypted_key);
+    trace.stage("iv", iv);
 
     // decrypt the payload
     let key = GenericArray::from_slice(&decrypted_key);
@@ -26,20 +68,20 @@ pub fn decode_aes_payload(b: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
     cipher
         .try_apply_keystream(&mut data)
         .map(|_| data)
-        .map_err(|e| format!("{}", e).into())
+        .map_err(|_| KeyUnwrapError::KeystreamOverflow)
+}
+
+fn read_u64(b: &[u8]) -> u64 {
+    u64::from_be_bytes(b.try_into().expect("8 bytes"))
 }
 
-fn unwrap_key(kek: &[u8], wrapped_key: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+fn unwrap_key(kek: &[u8], wkiv: &[u8], wrapped_key: &[u8]) -> Result<Vec<u8>, KeyUnwrapError> {
     // 1 byte for IV + 5 * 8 bytes in the key
     // use AES codebook = ECB, don't unpad!
     type Aes256Ecb = ecb::Decryptor<Aes256>;
 
-    let mut c = Cursor::new(wrapped_key);
-    let mut a = c.read_u64::<BigEndian>()?;
-    let mut r = repeat_with(|| c.read_u64::<BigEndian>())
-        .flatten()
-        .take(4)
-        .collect::<Vec<_>>();
+    let mut a = read_u64(&wrapped_key[..8]);
+    let mut r = wrapped_key[8..].chunks(8).map(read_u64).collect::<Vec<_>>();
 
     for j in (0..=5).rev() {
         for i in (1..=4).rev() {
@@ -49,16 +91,91 @@ fn unwrap_key(kek: &[u8], wrapped_key: &[u8]) -> Result<Vec<u8>, Box<dyn Error>>
             v.extend_from_slice(&r[i - 1].to_be_bytes());
 
             let cipher = Aes256Ecb::new(kek.into());
-            let b = cipher.decrypt_padded_vec_mut::<NoPadding>(&v)?;
+            let b = cipher
+                .decrypt_padded_vec_mut::<NoPadding>(&v)
+                .expect("whole block");
 
-            let mut c = Cursor::new(&b);
-
-            a = c.read_u64::<BigEndian>()?;
-            r[i - 1] = c.read_u64::<BigEndian>()?;
+            a = read_u64(&b[..8]);
+            r[i - 1] = read_u64(&b[8..]);
         }
     }
 
+    // the initial value is only recovered with the right key
+    if a != read_u64(wkiv) {
+        return Err(KeyUnwrapError::Integrity);
+    }
+
     Ok(r.into_iter()
         .flat_map(|v| Vec::from(v.to_be_bytes()))
         .collect())
 }
+
+pub struct AesLayer;
+
+impl Layer for AesLayer {
+    fn number(&self) -> u8 {
+        5
+    }
+
+    fn name(&self) -> &'static str {
+        "Advanced Encryption Standard"
+    }
+
+    fn description(&self) -> &'static str {
+        "Unwrap the AES key, then decrypt the payload."
+    }
+
+    fn decode(&self, payload: Vec<u8>) -> Result<Vec<u8>, PeelError> {
+        self.decode_traced(payload, &mut Trace::new())
+    }
+
+    fn decode_traced(&self, payload: Vec<u8>, trace: &mut Trace) -> Result<Vec<u8>, PeelError> {
+        decode_aes_payload_traced(&payload, trace).map_err(|source| PeelError::KeyUnwrap {
+            layer: self.number(),
+            source,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // key wrap test vector from RFC 3394, section 4.6
+    const KEK: &str = "000102030405060708090A0B0C0D0E0F101112131415161718191A1B1C1D1E1F";
+    const WRAPPED: &str =
+        "28C9F404C4B810F4CBCCB35CFB87F8263F5786E2D80ED326CBC7F0E71A99F43BFB988B9B7A02DD21";
+    const KEY: &str = "00112233445566778899AABBCCDDEEFF000102030405060708090A0B0C0D0E0F";
+    const WKIV: &str = "A6A6A6A6A6A6A6A6";
+
+    fn hex(s: &str) -> Vec<u8> {
+        data_encoding::HEXUPPER.decode(s.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_unwrap_key() {
+        assert_eq!(
+            unwrap_key(&hex(KEK), &hex(WKIV), &hex(WRAPPED)).unwrap(),
+            hex(KEY)
+        );
+    }
+
+    #[test]
+    fn test_integrity() {
+        let mut kek = hex(KEK);
+        kek[0] ^= 1;
+
+        assert_eq!(
+            unwrap_key(&kek, &hex(WKIV), &hex(WRAPPED)),
+            Err(KeyUnwrapError::Integrity)
+        );
+    }
+
+    #[test]
+    fn test_truncated() {
+        assert_eq!(
+            decode_aes_payload(&[0; 95]),
+            Err(KeyUnwrapError::Truncated(95))
+        );
+    }
+}
@@ -1,6 +1,36 @@
+use crate::dump::Trace;
+use crate::error::PeelError;
+use crate::layer::Layer;
 use byteorder::{LittleEndian, ReadBytesExt};
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
 use std::io::Write;
 
+/// Fault of a program, with the address of the faulting instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmError {
+    /// An instruction the CPU does not know.
+    InvalidOpcode { pc: u32, opcode: u8 },
+    /// An access outside of memory, including fetching past its end.
+    InvalidAddress { pc: u32, address: u32 },
+}
+
+impl Display for VmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::InvalidOpcode { pc, opcode } => {
+                write!(f, "invalid opcode {:#04x} at {:#x}", opcode, pc)
+            }
+            VmError::InvalidAddress { pc, address } => {
+                write!(f, "invalid memory access to {:#x} at {:#x}", address, pc)
+            }
+        }
+    }
+}
+
+impl Error for VmError {}
+
 enum Status {
     Continue,
     Stop,
@@ -44,7 +74,8 @@ enum Instr {
     Xor,
 }
 
-struct TomtelVm<W>
+/// The Tomtel Core i69, writing its output to `W`.
+pub struct TomtelVm<W>
 where
     W: Write,
 {
@@ -62,13 +93,15 @@ where
     pc: u32,
     output: W,
     memory: Vec<u8>,
+    steps: u64,
 }
 
 impl<W> TomtelVm<W>
 where
     W: Write,
 {
-    fn new(output: W, memory: &[u8]) -> Self {
+    /// Create a VM with zeroed registers and a copy of the program as memory.
+    pub fn new(output: W, memory: &[u8]) -> Self {
         Self {
             a: 0,
             b: 0,
@@ -84,101 +117,133 @@ where
             pc: 0,
             output,
             memory: memory.to_vec(),
+            steps: 0,
         }
     }
 
-    fn output(self) -> W {
+    pub fn output(self) -> W {
         self.output
     }
 
-    fn run(&mut self) {
+    /// Number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Execute instructions until `HALT`.
+    pub fn run(&mut self) -> Result<(), VmError> {
         loop {
-            let (instr, size) = self.fetch_instr();
-            self.pc += size as u32;
-            match self.exec(instr) {
-                Status::Stop => return,
+            let pc = self.pc;
+            let (instr, size) = self.fetch_instr()?;
+            self.pc = self.pc.wrapping_add(size as u32);
+            self.steps += 1;
+            match self
+                .exec(instr)
+                .map_err(|address| VmError::InvalidAddress { pc, address })?
+            {
+                Status::Stop => return Ok(()),
                 Status::Continue => continue,
             }
         }
     }
 
-    fn fetch_instr(&self) -> (Instr, u8) {
-        let b = self.memory[self.pc as usize];
+    fn fetch_instr(&self) -> Result<(Instr, u8), VmError> {
+        let b = self.read(self.pc)?;
 
-        match b {
+        Ok(match b {
             0x01 => (Instr::Halt, 1),
             0x02 => (Instr::Out, 1),
-            0x21 => (Instr::Jez(self.imm32()), 5),
-            0x22 => (Instr::Jnz(self.imm32()), 5),
+            0x21 => (Instr::Jez(self.imm32()?), 5),
+            0x22 => (Instr::Jnz(self.imm32()?), 5),
             0xC1 => (Instr::Cmp, 1),
             0xC2 => (Instr::Add, 1),
             0xC3 => (Instr::Sub, 1),
             0xC4 => (Instr::Xor, 1),
-            0xE1 => (Instr::Aptr(self.imm8()), 2),
-            b => self.parse_mv(b),
-        }
+            0xE1 => (Instr::Aptr(self.imm8()?), 2),
+            b => self.parse_mv(b)?,
+        })
     }
 
-    fn parse_mv(&self, instr: u8) -> (Instr, u8) {
+    fn parse_mv(&self, instr: u8) -> Result<(Instr, u8), VmError> {
         let opcode = (instr & 0b11000000) >> 6;
         let dest = (instr & 0b00111000) >> 3;
         let src = instr & 0b00000111;
 
-        match (opcode, src) {
-            (0b01, 0) => (Instr::Mvi(Self::parse_mv_arg(dest), self.imm8()), 2),
-            (0b01, src) => (
-                Instr::Mv(Self::parse_mv_arg(dest), Self::parse_mv_arg(src)),
-                1,
-            ),
-            (0b10, 0) => (Instr::Mvi32(Self::parse_mv32_arg(dest), self.imm32()), 5),
-            (0b10, src) => (
-                Instr::Mv32(Self::parse_mv32_arg(dest), Self::parse_mv32_arg(src)),
-                1,
-            ),
-            _ => unreachable!(),
-        }
+        let invalid = VmError::InvalidOpcode {
+            pc: self.pc,
+            opcode: instr,
+        };
+        let mv = |arg| Self::parse_mv_arg(arg).ok_or(invalid);
+        let mv32 = |arg| Self::parse_mv32_arg(arg).ok_or(invalid);
+
+        Ok(match (opcode, src) {
+            (0b01, 0) => (Instr::Mvi(mv(dest)?, self.imm8()?), 2),
+            (0b01, src) => (Instr::Mv(mv(dest)?, mv(src)?), 1),
+            (0b10, 0) => (Instr::Mvi32(mv32(dest)?, self.imm32()?), 5),
+            (0b10, src) => (Instr::Mv32(mv32(dest)?, mv32(src)?), 1),
+            _ => return Err(invalid),
+        })
     }
 
-    fn parse_mv_arg(arg: u8) -> MvArg {
+    fn parse_mv_arg(arg: u8) -> Option<MvArg> {
         match arg {
-            1 => MvArg::A,
-            2 => MvArg::B,
-            3 => MvArg::C,
-            4 => MvArg::D,
-            5 => MvArg::E,
-            6 => MvArg::F,
-            7 => MvArg::PtrC,
-            _ => unreachable!(),
+            1 => Some(MvArg::A),
+            2 => Some(MvArg::B),
+            3 => Some(MvArg::C),
+            4 => Some(MvArg::D),
+            5 => Some(MvArg::E),
+            6 => Some(MvArg::F),
+            7 => Some(MvArg::PtrC),
+            _ => None,
         }
     }
 
-    fn parse_mv32_arg(arg: u8) -> Mv32Arg {
+    fn parse_mv32_arg(arg: u8) -> Option<Mv32Arg> {
         match arg {
-            1 => Mv32Arg::La,
-            2 => Mv32Arg::Lb,
-            3 => Mv32Arg::Lc,
-            4 => Mv32Arg::Ld,
-            5 => Mv32Arg::Ptr,
-            6 => Mv32Arg::Pc,
-            _ => unreachable!(),
+            1 => Some(Mv32Arg::La),
+            2 => Some(Mv32Arg::Lb),
+            3 => Some(Mv32Arg::Lc),
+            4 => Some(Mv32Arg::Ld),
+            5 => Some(Mv32Arg::Ptr),
+            6 => Some(Mv32Arg::Pc),
+            _ => None,
         }
     }
 
-    fn imm8(&self) -> u8 {
-        self.memory[self.pc as usize + 1]
+    fn read(&self, address: u32) -> Result<u8, VmError> {
+        self.memory
+            .get(address as usize)
+            .copied()
+            .ok_or(VmError::InvalidAddress {
+                pc: self.pc,
+                address,
+            })
+    }
+
+    fn imm8(&self) -> Result<u8, VmError> {
+        self.read(self.pc.wrapping_add(1))
     }
 
-    fn imm32(&self) -> u32 {
-        let mut rdr = &self.memory[self.pc as usize + 1..];
-        rdr.read_u32::<LittleEndian>().unwrap_or(0)
+    fn imm32(&self) -> Result<u32, VmError> {
+        let start = self.pc.wrapping_add(1);
+        let mut rdr =
+            self.memory
+                .get(start as usize..start as usize + 4)
+                .ok_or(VmError::InvalidAddress {
+                    pc: self.pc,
+                    address: start,
+                })?;
+        Ok(rdr.read_u32::<LittleEndian>().expect("4 bytes"))
     }
 
-    fn exec(&mut self, instr: Instr) -> Status {
+    /// Execute an instruction, failing with the address of an invalid
+    /// memory access.
+    fn exec(&mut self, instr: Instr) -> Result<Status, u32> {
         match instr {
             Instr::Add => self.a = self.a.overflowing_add(self.b).0,
-            Instr::Aptr(imm8) => self.ptr += imm8 as u32,
+            Instr::Aptr(imm8) => self.ptr = self.ptr.wrapping_add(imm8 as u32),
             Instr::Cmp => self.f = if self.a == self.b { 0 } else { 1 },
-            Instr::Halt => return Status::Stop,
+            Instr::Halt => return Ok(Status::Stop),
             Instr::Jez(imm32) => {
                 if self.f == 0 {
                     self.pc = imm32
@@ -189,9 +254,9 @@ where
                     self.pc = imm32
                 }
             }
-            Instr::Mv(dest, src) => *self.get_reg8_mut_ref(dest) = self.get_reg8_value(src),
+            Instr::Mv(dest, src) => *self.get_reg8_mut_ref(dest)? = self.get_reg8_value(src)?,
             Instr::Mv32(dest, src) => *self.get_reg32_mut_ref(dest) = self.get_reg32_value(src),
-            Instr::Mvi(dest, imm8) => *self.get_reg8_mut_ref(dest) = imm8,
+            Instr::Mvi(dest, imm8) => *self.get_reg8_mut_ref(dest)? = imm8,
             Instr::Mvi32(dest, imm32) => *self.get_reg32_mut_ref(dest) = imm32,
             Instr::Out => {
                 let _ = self.output.write(&[self.a]);
@@ -200,11 +265,11 @@ where
             Instr::Xor => self.a ^= self.b,
         }
 
-        Status::Continue
+        Ok(Status::Continue)
     }
 
-    fn get_reg8_value(&self, arg: MvArg) -> u8 {
-        match arg {
+    fn get_reg8_value(&self, arg: MvArg) -> Result<u8, u32> {
+        Ok(match arg {
             MvArg::A => self.a,
             MvArg::B => self.b,
             MvArg::C => self.c,
@@ -212,14 +277,14 @@ where
             MvArg::E => self.e,
             MvArg::F => self.f,
             MvArg::PtrC => {
-                let idx = self.ptr + self.c as u32;
-                self.memory[idx as usize]
+                let idx = self.ptr.wrapping_add(self.c as u32);
+                *self.memory.get(idx as usize).ok_or(idx)?
             }
-        }
+        })
     }
 
-    fn get_reg8_mut_ref(&mut self, arg: MvArg) -> &mut u8 {
-        match arg {
+    fn get_reg8_mut_ref(&mut self, arg: MvArg) -> Result<&mut u8, u32> {
+        Ok(match arg {
             MvArg::A => &mut self.a,
             MvArg::B => &mut self.b,
             MvArg::C => &mut self.c,
@@ -227,12 +292,10 @@ where
             MvArg::E => &mut self.e,
             MvArg::F => &mut self.f,
             MvArg::PtrC => {
-                let idx = self.ptr + self.c as u32;
-                self.memory
-                    .get_mut(idx as usize)
-                    .expect("invalid memory access")
+                let idx = self.ptr.wrapping_add(self.c as u32);
+                self.memory.get_mut(idx as usize).ok_or(idx)?
             }
-        }
+        })
     }
 
     fn get_reg32_value(&self, arg: Mv32Arg) -> u32 {
@@ -258,11 +321,47 @@ where
     }
 }
 
-pub fn run_payload_program(program: &[u8]) -> Vec<u8> {
+pub fn run_payload_program(program: &[u8]) -> Result<Vec<u8>, VmError> {
+    run_payload_program_traced(program, &mut Trace::new())
+}
+
+/// Run a program as `run_payload_program`, recording the number of
+/// instructions executed, even when it faults.
+pub fn run_payload_program_traced(program: &[u8], trace: &mut Trace) -> Result<Vec<u8>, VmError> {
     let out = vec![];
     let mut vm = TomtelVm::new(out, program);
-    vm.run();
-    vm.output()
+    let result = vm.run();
+    trace.stat("instructions", vm.steps());
+
+    result?;
+    Ok(vm.output())
+}
+
+pub struct VmLayer;
+
+impl Layer for VmLayer {
+    fn number(&self) -> u8 {
+        6
+    }
+
+    fn name(&self) -> &'static str {
+        "Virtual Machine"
+    }
+
+    fn description(&self) -> &'static str {
+        "Run the payload as a Tomtel Core i69 program."
+    }
+
+    fn decode(&self, payload: Vec<u8>) -> Result<Vec<u8>, PeelError> {
+        self.decode_traced(payload, &mut Trace::new())
+    }
+
+    fn decode_traced(&self, payload: Vec<u8>, trace: &mut Trace) -> Result<Vec<u8>, PeelError> {
+        run_payload_program_traced(&payload, trace).map_err(|source| PeelError::Vm {
+            layer: self.number(),
+            source,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -281,9 +380,48 @@ mod tests {
         ];
         let out = vec![];
         let mut vm = TomtelVm::new(out, &program);
-        vm.run();
+        vm.run().unwrap();
         let output = String::from_utf8(vm.output());
 
         assert_eq!(output.unwrap().as_str(), "Hello, world!");
     }
+
+    #[test]
+    fn test_steps() {
+        // OUT a, OUT a, HALT
+        let mut vm = TomtelVm::new(vec![], &[0x02, 0x02, 0x01]);
+        vm.run().unwrap();
+
+        assert_eq!(vm.steps(), 3);
+        assert_eq!(vm.output(), [0, 0]);
+    }
+
+    #[test]
+    fn test_faults() {
+        // no HALT before the end of memory
+        assert_eq!(
+            run_payload_program(&[0x02]),
+            Err(VmError::InvalidAddress { pc: 1, address: 1 })
+        );
+        assert_eq!(
+            run_payload_program(&[0x02, 0xFF]),
+            Err(VmError::InvalidOpcode {
+                pc: 1,
+                opcode: 0xFF
+            })
+        );
+        // MVI32 with a truncated operand
+        assert_eq!(
+            run_payload_program(&[0xA8, 0x01]),
+            Err(VmError::InvalidAddress { pc: 0, address: 1 })
+        );
+        // MVI ptr, 0x40 then MV a, (ptr+c)
+        assert_eq!(
+            run_payload_program(&[0xA8, 0x40, 0, 0, 0, 0x4F, 0x01]),
+            Err(VmError::InvalidAddress {
+                pc: 5,
+                address: 0x40
+            })
+        );
+    }
 }
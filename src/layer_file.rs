@@ -0,0 +1,203 @@
+//! Parsing of layer files.
+//!
+//! A layer file may start with free text, such as the introduction of the
+//! first layer, followed by a header, instructions, and the payload:
+//!
+//! ```text
+//! ==[ Layer 3/6: XOR Encryption ]=============================
+//!
+//! Instructions...
+//!
+//! ==[ Payload ]===============================================
+//!
+//! <~...~>
+//! ```
+
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_until, take_while};
+use nom::character::complete::{digit1, line_ending, space0};
+use nom::combinator::{eof, map_res};
+use nom::sequence::{delimited, preceded, terminated, tuple};
+use nom::IResult;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::Utf8Error;
+
+const HEADER_START: &str = "==[ Layer ";
+const PAYLOAD_START: &str = "==[ Payload ]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerFileError {
+    /// No `==[ Layer N/M: Title ]` line.
+    MissingHeader,
+    /// A header line which cannot be parsed, at the given line.
+    InvalidHeader(usize),
+    /// No `==[ Payload ]` line after the header.
+    MissingPayload,
+    /// A layer which is not UTF-8 text.
+    NotText(Utf8Error),
+    /// A header with another number than the one of the layer expected.
+    UnexpectedNumber { expected: u8, found: u8 },
+}
+
+impl Display for LayerFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LayerFileError::MissingHeader => write!(f, "missing layer header"),
+            LayerFileError::InvalidHeader(line) => {
+                write!(f, "invalid layer header at line {}", line)
+            }
+            LayerFileError::MissingPayload => write!(f, "missing payload section"),
+            LayerFileError::NotText(e) => write!(f, "layer is not text, {}", e),
+            LayerFileError::UnexpectedNumber { expected, found } => {
+                write!(f, "expected layer {}, found layer {}", expected, found)
+            }
+        }
+    }
+}
+
+impl Error for LayerFileError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerFile<'a> {
+    /// Number of the layer, at most `total`, and less than 255 so that the
+    /// next layer has a number too.
+    pub number: u8,
+    /// Number of the last layer.
+    pub total: u8,
+    pub title: &'a str,
+    /// Text between the header and the payload, without surrounding blank
+    /// lines.
+    pub instructions: &'a str,
+    /// Encoded payload, as found after the payload line.
+    pub payload: &'a str,
+}
+
+fn number(i: &str) -> IResult<&str, u8> {
+    map_res(digit1, str::parse)(i)
+}
+
+/// Parse a header line, up to and including its line ending.
+fn header(i: &str) -> IResult<&str, (u8, u8, &str)> {
+    let (i, (number, total, title)) = tuple((
+        preceded(tag(HEADER_START), number),
+        preceded(tag("/"), number),
+        delimited(tag(": "), take_until(" ]"), tag(" ]")),
+    ))(i)?;
+    let (i, _) = terminated(
+        take_while(|c| c == '='),
+        preceded(space0, alt((line_ending, eof))),
+    )(i)?;
+
+    Ok((i, (number, total, title)))
+}
+
+/// Find the start of a line beginning with `prefix`.
+fn find_line(s: &str, prefix: &str) -> Option<usize> {
+    if s.starts_with(prefix) {
+        return Some(0);
+    }
+    s.match_indices(&format!("\n{}", prefix))
+        .next()
+        .map(|(i, _)| i + 1)
+}
+
+impl<'a> LayerFile<'a> {
+    pub fn parse(s: &'a str) -> Result<Self, LayerFileError> {
+        let start = find_line(s, HEADER_START).ok_or(LayerFileError::MissingHeader)?;
+        let invalid_header = || LayerFileError::InvalidHeader(s[..start].matches('\n').count() + 1);
+        let (rest, (number, total, title)) = header(&s[start..]).map_err(|_| invalid_header())?;
+        if number > total || number == u8::MAX {
+            return Err(invalid_header());
+        }
+
+        let end = find_line(rest, PAYLOAD_START).ok_or(LayerFileError::MissingPayload)?;
+        let instructions = rest[..end].trim_matches(|c| c == '\n' || c == '\r');
+
+        let payload = &rest[end + PAYLOAD_START.len()..];
+        let payload = payload.trim_start_matches('=');
+
+        Ok(Self {
+            number,
+            total,
+            title,
+            instructions,
+            payload,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYER: &str = "\
+==[ Layer 3/6: XOR Encryption ]=============================
+
+Some instructions.
+
+With two paragraphs.
+
+==[ Payload ]===============================================
+
+<~z~>
+";
+
+    #[test]
+    fn test_parse() {
+        let file = LayerFile::parse(LAYER).unwrap();
+
+        assert_eq!(file.number, 3);
+        assert_eq!(file.total, 6);
+        assert_eq!(file.title, "XOR Encryption");
+        assert_eq!(
+            file.instructions,
+            "Some instructions.\n\nWith two paragraphs."
+        );
+        assert_eq!(file.payload, "\n\n<~z~>\n");
+    }
+
+    #[test]
+    fn test_parse_first_layer() {
+        let file = LayerFile::parse(include_str!("../layers/00.txt")).unwrap();
+
+        assert_eq!((file.number, file.total), (0, 6));
+        assert_eq!(file.title, "ASCII85");
+        assert!(file.instructions.starts_with("ASCII85 is a binary-to-text"));
+        assert!(file
+            .instructions
+            .ends_with("Decode the payload below to proceed!"));
+        assert!(file.payload.trim().starts_with("<~"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            LayerFile::parse("==[ The Core ]====\n\nDone."),
+            Err(LayerFileError::MissingHeader)
+        );
+        assert_eq!(
+            LayerFile::parse("intro\n==[ Layer 3: XOR ]===\n"),
+            Err(LayerFileError::InvalidHeader(2))
+        );
+        assert_eq!(
+            LayerFile::parse("==[ Layer 3/6: XOR ]===\n\nNo payload.\n"),
+            Err(LayerFileError::MissingPayload)
+        );
+        // the layer number must be in range
+        assert_eq!(
+            LayerFile::parse("==[ Layer 7/6: XOR ]===\n==[ Payload ]\n"),
+            Err(LayerFileError::InvalidHeader(1))
+        );
+        assert_eq!(
+            LayerFile::parse("==[ Layer 255/255: XOR ]===\n==[ Payload ]\n"),
+            Err(LayerFileError::InvalidHeader(1))
+        );
+        // the header must be at the start of a line
+        assert_eq!(
+            LayerFile::parse("see ==[ Layer 3/6: XOR ]===\n==[ Payload ]\n"),
+            Err(LayerFileError::MissingHeader)
+        );
+    }
+}
@@ -0,0 +1,33 @@
+//! Solver for Tom's Data Onion, with its decoders usable on their own.
+
+pub mod ascii85;
+pub mod codec;
+pub mod detect;
+pub mod dump;
+pub mod error;
+pub mod layer;
+/// Layer 0: ASCII85.
+pub mod layer0;
+/// Layer 1: bitwise operations.
+pub mod layer1;
+/// Layer 2: parity bits.
+pub mod layer2;
+/// Layer 3: XOR encryption.
+pub mod layer3;
+/// Layer 4: network traffic.
+pub mod layer4;
+/// Layer 5: advanced encryption.
+pub mod layer5;
+/// Layer 6: virtual machine.
+pub mod layer6;
+pub mod layer_file;
+pub mod naming;
+pub mod pipeline;
+pub mod report;
+
+pub use error::PeelError;
+pub use layer::{Layer, Registry};
+pub use layer6::TomtelVm;
+pub use layer_file::LayerFile;
+pub use naming::LayerNaming;
+pub use report::PeelReport;
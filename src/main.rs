@@ -1,116 +1,285 @@
-use crate::ascii85::{decode_ascii85_str, DecodeError};
-use crate::layer1::flip_and_rotate;
-use crate::layer2::parse_parity_buffer;
-use crate::layer3::decode_xor_encoded_payload;
-use crate::layer4::parse_ip_payload;
-use crate::layer5::decode_aes_payload;
-use crate::layer6::run_payload_program;
-use nom::lib::std::fmt::Formatter;
-use std::error::Error;
-use std::fmt::Display;
-use std::fs::File;
-use std::io::Write;
-use std::path::Path;
-use std::{fmt, fs, io};
-
-mod ascii85;
-mod layer1;
-mod layer2;
-mod layer3;
-mod layer4;
-mod layer5;
-mod layer6;
-
-#[derive(Debug)]
-struct ProblemError {
-    inner: Box<dyn Error>,
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use data_onion::codec::codec_by_name;
+use data_onion::detect::detect_encoding;
+use data_onion::pipeline::{
+    check_output, find_resume_layer, peel, read_layer, resolve_layer, save_layer, solve_layer,
+    solve_layer_dumped,
+};
+use data_onion::{LayerFile, LayerNaming, PeelError, PeelReport, Registry};
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  success
+  2  invalid command line
+  3  input or output error
+  4  invalid layer, not text or without a header, payload or solver
+  5  payload which cannot be decoded
+  6  layer transform failure";
+
+/// Peel the layers of Tom's Data Onion.
+#[derive(Parser)]
+#[command(version, after_help = EXIT_CODES)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-impl Display for ProblemError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.inner)
-    }
+#[derive(Subcommand)]
+enum Command {
+    /// Peel every layer, saving each one to the output directory
+    Peel {
+        /// First layer, `-` for stdin [default: the lowest-numbered layer
+        /// file in the output directory]
+        #[arg(long)]
+        input: Option<PathBuf>,
+        #[arg(long, default_value = "layers")]
+        output_dir: PathBuf,
+        /// Continue from the deepest valid layer file in the output directory
+        #[arg(long, conflicts_with = "input")]
+        resume: bool,
+        /// Regenerate the layers following this one, from its file in the
+        /// output directory
+        #[arg(long, value_name = "LAYER", conflicts_with_all = ["input", "resume"])]
+        from: Option<u8>,
+        /// Directory to dump the intermediate buffers of every layer to
+        #[arg(long)]
+        dump_dir: Option<PathBuf>,
+        /// File to write a JSON report of the peel to, `-` for stdout
+        #[arg(long)]
+        report: Option<PathBuf>,
+        #[command(flatten)]
+        naming: NamingArgs,
+    },
+    /// Solve a single layer
+    Layer {
+        /// Solver to use [default: the one matching the layer header]
+        number: Option<u8>,
+        /// Layer file, `-` for stdin
+        #[arg(long, default_value = "-")]
+        input: PathBuf,
+        /// Directory to save the next layer to, instead of stdout
+        #[arg(long)]
+        output_dir: Option<PathBuf>,
+        /// Directory to dump the intermediate buffers of the layer to
+        #[arg(long)]
+        dump_dir: Option<PathBuf>,
+        #[command(flatten)]
+        naming: NamingArgs,
+    },
+    /// Decode a raw payload to stdout
+    Decode {
+        /// Encoding of the payload, or `auto` to detect it
+        #[arg(long, default_value = "ascii85", value_parser = parse_codec)]
+        codec: String,
+        /// Payload file, `-` for stdin
+        #[arg(long, default_value = "-")]
+        input: PathBuf,
+    },
 }
 
-impl From<DecodeError> for ProblemError {
-    fn from(e: DecodeError) -> Self {
-        Self { inner: e.into() }
-    }
+/// Naming of the layer files in the output directory.
+#[derive(Args, Default)]
+struct NamingArgs {
+    /// Text before the layer number in file names [default: none]
+    #[arg(long)]
+    prefix: Option<String>,
+    /// Minimum number of digits of the layer number [default: 2]
+    #[arg(long)]
+    width: Option<usize>,
+    /// Text after the layer number in file names [default: .txt]
+    #[arg(long)]
+    suffix: Option<String>,
 }
 
-impl From<Box<dyn Error>> for ProblemError {
-    fn from(e: Box<dyn Error>) -> Self {
-        Self { inner: e }
+impl NamingArgs {
+    fn naming(self) -> LayerNaming {
+        let default = LayerNaming::default();
+        LayerNaming {
+            prefix: self.prefix.unwrap_or(default.prefix),
+            width: self.width.unwrap_or(default.width),
+            suffix: self.suffix.unwrap_or(default.suffix),
+        }
     }
 }
 
-impl Error for ProblemError {}
-
-fn extract_payload(s: &str) -> Option<&str> {
-    const DELIM: &str = "==[ Payload ]===============================================";
-    s.find(DELIM).map(|i| &s[i + DELIM.len()..])
+fn parse_codec(s: &str) -> Result<String, String> {
+    if s == "auto" || codec_by_name(s).is_some() {
+        Ok(s.to_string())
+    } else {
+        Err(format!("unknown codec `{}`", s))
+    }
 }
 
-fn save_layer(p: impl AsRef<Path>, b: &[u8]) -> io::Result<()> {
-    let mut f = File::create(p)?;
-    f.write_all(b)
+fn read_input(p: &Path) -> io::Result<String> {
+    if p == Path::new("-") {
+        let mut s = String::new();
+        io::stdin().read_to_string(&mut s)?;
+        Ok(s)
+    } else {
+        fs::read_to_string(p)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", p.display(), e)))
+    }
 }
 
-fn solve_layer<F>(n: u8, f: F) -> Result<(), Box<dyn Error>>
-where
-    F: Fn(&str) -> Result<Vec<u8>, ProblemError>,
-{
-    let s = fs::read_to_string(format!("layers/0{}.txt", n))?;
-    let p = extract_payload(&s).ok_or("Unable to extract payload")?;
-    let b = f(p).map_err(|e| e.to_string())?;
-    save_layer(format!("layers/0{}.txt", n + 1), &b)?;
-    Ok(())
+fn write_output(b: &[u8]) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    match out.write_all(b).and_then(|_| out.flush()) {
+        // the reader has seen enough, as with `head`
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        r => r,
+    }
 }
 
-fn solve_layer00(s: &str) -> Result<Vec<u8>, ProblemError> {
-    decode_ascii85_str(s).map_err(ProblemError::from)
-}
+fn decode(codec: &str, s: &str) -> Result<Vec<u8>, PeelError> {
+    let encoding_error = |source| PeelError::Encoding {
+        layer: None,
+        source,
+    };
 
-fn solve_layer01(s: &str) -> Result<Vec<u8>, ProblemError> {
-    let buffer = decode_ascii85_str(s)?;
-    Ok(buffer.into_iter().map(flip_and_rotate).collect())
+    match codec_by_name(codec) {
+        Some(codec) => codec.decode(s).map_err(encoding_error),
+        None => {
+            let candidate = detect_encoding(s)[0];
+            eprintln!(
+                "detected {} with {:.1}% confidence",
+                candidate.encoding,
+                candidate.confidence * 100.0
+            );
+            candidate.encoding.decode(s).map_err(encoding_error)
+        }
+    }
 }
 
-fn solve_layer02(s: &str) -> Result<Vec<u8>, ProblemError> {
-    let buffer = decode_ascii85_str(s)?;
-    Ok(parse_parity_buffer(&buffer))
-}
+fn run(registry: &Registry, command: Command) -> Result<(), PeelError> {
+    match command {
+        Command::Peel {
+            input,
+            output_dir,
+            resume,
+            from,
+            dump_dir,
+            report,
+            naming,
+        } => {
+            let naming = naming.naming();
+            let start = match from {
+                Some(n) => Some(n),
+                None if resume => find_resume_layer(&output_dir, &naming)?,
+                None => None,
+            };
 
-fn solve_layer03(s: &str) -> Result<Vec<u8>, ProblemError> {
-    let buffer = decode_ascii85_str(s)?;
-    decode_xor_encoded_payload(&buffer).ok_or_else(|| ProblemError {
-        inner: "unable to decode xor-encoded payload".into(),
-    })
-}
+            let first = match (start, input) {
+                (Some(n), _) => {
+                    eprintln!("resuming from layer {}", n);
+                    read_layer(naming.path(&output_dir, n), n)?
+                }
+                (None, Some(input)) => read_input(&input)?,
+                (None, None) => match naming.discover(&output_dir)?.into_iter().next() {
+                    Some((_, path)) => read_input(&path)?,
+                    None => read_input(&naming.path(&output_dir, 0))?,
+                },
+            };
+            let mut peel_report = PeelReport::default();
+            let result = peel(
+                registry,
+                &first,
+                &output_dir,
+                &naming,
+                dump_dir.as_deref(),
+                report.is_some().then_some(&mut peel_report),
+            );
 
-fn solve_layer04(s: &str) -> Result<Vec<u8>, ProblemError> {
-    let buffer = decode_ascii85_str(s)?;
-    parse_ip_payload(&buffer).map_err(ProblemError::from)
-}
+            // the report also tells how far a failed peel went
+            if let Some(path) = report {
+                peel_report.error = result.as_ref().err().map(|e| e.to_string());
+                let json =
+                    serde_json::to_string_pretty(&peel_report).expect("serializable report") + "\n";
+                if path == Path::new("-") {
+                    write_output(json.as_bytes())?;
+                } else {
+                    fs::write(path, json)?;
+                }
+            }
+            result?;
+        }
+        Command::Layer {
+            number,
+            input,
+            output_dir,
+            dump_dir,
+            naming,
+        } => {
+            let s = read_input(&input)?;
+            let file = LayerFile::parse(&s)?;
+            let layer = match number {
+                Some(n) => registry.get(n).unwrap_or_else(|| {
+                    let numbers = registry
+                        .iter()
+                        .map(|l| l.number().to_string())
+                        .collect::<Vec<_>>();
+                    let message = if numbers.is_empty() {
+                        format!("unknown layer {}, no layers are registered", n)
+                    } else {
+                        format!(
+                            "unknown layer {}, expected one of {}",
+                            n,
+                            numbers.join(", ")
+                        )
+                    };
+                    Cli::command()
+                        .error(ErrorKind::InvalidValue, message)
+                        .exit()
+                }),
+                None => resolve_layer(registry, &file)?,
+            };
 
-fn solve_layer05(s: &str) -> Result<Vec<u8>, ProblemError> {
-    let buffer = decode_ascii85_str(s)?;
-    decode_aes_payload(&buffer).map_err(ProblemError::from)
-}
+            let b = match dump_dir {
+                Some(dump) => solve_layer_dumped(layer, &file, dump)?,
+                None => solve_layer(layer, &file)?,
+            };
+            let s = check_output(&file, b)?;
+            match output_dir {
+                Some(dir) => save_layer(naming.naming().path(dir, file.number + 1), s.as_bytes())?,
+                None => write_output(s.as_bytes())?,
+            }
+        }
+        Command::Decode { codec, input } => {
+            let b = decode(&codec, &read_input(&input)?)?;
+            write_output(&b)?;
+        }
+    }
 
-fn solve_layer06(s: &str) -> Result<Vec<u8>, ProblemError> {
-    let buffer = decode_ascii85_str(s)?;
-    Ok(run_payload_program(&buffer))
+    Ok(())
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    solve_layer(0, solve_layer00)?;
-    solve_layer(1, solve_layer01)?;
-    solve_layer(2, solve_layer02)?;
-    solve_layer(3, solve_layer03)?;
-    solve_layer(4, solve_layer04)?;
-    solve_layer(5, solve_layer05)?;
-    solve_layer(6, solve_layer06)?;
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Peel {
+        input: None,
+        output_dir: PathBuf::from("layers"),
+        resume: false,
+        from: None,
+        dump_dir: None,
+        report: None,
+        naming: NamingArgs::default(),
+    });
 
-    Ok(())
+    match run(&Registry::standard(), command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(match e {
+                PeelError::Io { .. } => 3,
+                PeelError::Payload { .. } | PeelError::UnknownLayer { .. } => 4,
+                PeelError::Encoding { .. } => 5,
+                _ => 6,
+            })
+        }
+    }
 }
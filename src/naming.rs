@@ -0,0 +1,132 @@
+//! Naming of layer files, and their discovery in a directory.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Scheme of layer file names: a prefix, the layer number zero-padded to a
+/// minimum width, and a suffix, as in `03.txt`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerNaming {
+    pub prefix: String,
+    /// Minimum number of digits, numbers which need more are not truncated.
+    pub width: usize,
+    pub suffix: String,
+}
+
+impl Default for LayerNaming {
+    fn default() -> Self {
+        Self {
+            prefix: String::new(),
+            width: 2,
+            suffix: String::from(".txt"),
+        }
+    }
+}
+
+impl LayerNaming {
+    pub fn file_name(&self, n: u8) -> String {
+        format!(
+            "{}{:0width$}{}",
+            self.prefix,
+            n,
+            self.suffix,
+            width = self.width
+        )
+    }
+
+    pub fn path(&self, dir: impl AsRef<Path>, n: u8) -> PathBuf {
+        dir.as_ref().join(self.file_name(n))
+    }
+
+    /// Get the layer number of a file name following the scheme.
+    pub fn parse(&self, name: &str) -> Option<u8> {
+        let digits = name
+            .strip_prefix(&self.prefix)?
+            .strip_suffix(&self.suffix)?;
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        // only accept the canonical name, `3.txt` is not `03.txt`
+        let n = digits.parse().ok()?;
+        (self.file_name(n) == name).then_some(n)
+    }
+
+    /// Find the layer files in a directory, ordered by number.
+    pub fn discover(&self, dir: impl AsRef<Path>) -> io::Result<Vec<(u8, PathBuf)>> {
+        let mut files = vec![];
+
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let n = entry.file_name().to_str().and_then(|name| self.parse(name));
+            if let Some(n) = n {
+                if entry.file_type()?.is_file() {
+                    files.push((n, entry.path()));
+                }
+            }
+        }
+        files.sort();
+
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom() -> LayerNaming {
+        LayerNaming {
+            prefix: String::from("layer-"),
+            width: 3,
+            suffix: String::from(".onion"),
+        }
+    }
+
+    #[test]
+    fn test_file_name() {
+        let naming = LayerNaming::default();
+        assert_eq!(naming.file_name(3), "03.txt");
+        assert_eq!(naming.file_name(10), "10.txt");
+        assert_eq!(naming.file_name(123), "123.txt");
+
+        assert_eq!(custom().file_name(7), "layer-007.onion");
+        assert_eq!(
+            custom().path("layers", 12),
+            Path::new("layers/layer-012.onion")
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let naming = LayerNaming::default();
+        assert_eq!(naming.parse("03.txt"), Some(3));
+        assert_eq!(naming.parse("123.txt"), Some(123));
+        assert_eq!(naming.parse("3.txt"), None);
+        assert_eq!(naming.parse("003.txt"), None);
+        assert_eq!(naming.parse("+3.txt"), None);
+        assert_eq!(naming.parse("03.txt.bak"), None);
+        assert_eq!(naming.parse("999.txt"), None);
+
+        assert_eq!(custom().parse("layer-012.onion"), Some(12));
+        assert_eq!(custom().parse("012.onion"), None);
+    }
+
+    #[test]
+    fn test_discover() {
+        let dir = std::env::temp_dir().join(format!("data-onion-naming-{}", std::process::id()));
+        fs::create_dir_all(dir.join("04.txt")).unwrap();
+        for name in ["10.txt", "02.txt", "notes.txt", "1.txt"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let files = LayerNaming::default().discover(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            files.unwrap(),
+            [(2, dir.join("02.txt")), (10, dir.join("10.txt"))]
+        );
+    }
+}
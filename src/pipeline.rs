@@ -0,0 +1,368 @@
+//! Peeling of the onion, one layer file after the other.
+
+use crate::dump::{write_dump, Trace};
+use crate::error::PeelError;
+use crate::layer::{Layer, Registry};
+use crate::layer_file::{LayerFile, LayerFileError};
+use crate::naming::LayerNaming;
+use crate::report::{LayerReport, PeelReport};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::time::Instant;
+
+pub fn save_layer(p: impl AsRef<Path>, b: &[u8]) -> io::Result<()> {
+    let mut f = File::create(p)?;
+    f.write_all(b)
+}
+
+/// Check that a layer file parses, with the header of layer `n`.
+pub fn check_layer(s: &str, n: u8) -> Result<LayerFile<'_>, LayerFileError> {
+    let file = LayerFile::parse(s)?;
+    if file.number != n {
+        return Err(LayerFileError::UnexpectedNumber {
+            expected: n,
+            found: file.number,
+        });
+    }
+    Ok(file)
+}
+
+/// Read the file of layer `n`, checking its header.
+pub fn read_layer(p: impl AsRef<Path>, n: u8) -> Result<String, PeelError> {
+    let s = fs::read_to_string(p).map_err(|source| PeelError::Io {
+        layer: Some(n),
+        source,
+    })?;
+    check_layer(&s, n).map_err(|source| PeelError::Payload {
+        layer: Some(n),
+        source,
+    })?;
+    Ok(s)
+}
+
+/// Find the deepest layer peeling can resume from: the last of the valid
+/// layer files numbered consecutively from the first one in `dir`.
+pub fn find_resume_layer(dir: impl AsRef<Path>, naming: &LayerNaming) -> io::Result<Option<u8>> {
+    let mut deepest = None;
+
+    for (n, path) in naming.discover(dir)? {
+        if deepest.is_some_and(|d| d + 1 != n) {
+            break;
+        }
+        let valid = fs::read_to_string(path).is_ok_and(|s| check_layer(&s, n).is_ok());
+        if !valid {
+            break;
+        }
+        deepest = Some(n);
+    }
+
+    Ok(deepest)
+}
+
+/// Find the layer solving a file, from its title or number.
+pub fn resolve_layer<'r>(
+    registry: &'r Registry,
+    file: &LayerFile,
+) -> Result<&'r dyn Layer, PeelError> {
+    registry
+        .resolve(file)
+        .ok_or_else(|| PeelError::UnknownLayer {
+            layer: file.number,
+            title: file.title.to_string(),
+        })
+}
+
+/// Solve a layer file, returning the next layer.
+pub fn solve_layer(layer: &dyn Layer, file: &LayerFile) -> Result<Vec<u8>, PeelError> {
+    solve_layer_traced(layer, file, &mut Trace::new())
+}
+
+/// Solve a layer file as `solve_layer`, recording the decoded payload, the
+/// stages of the transform and the output to `trace`. Errors carry the number
+/// of the file, not the one of its solver.
+pub fn solve_layer_traced(
+    layer: &dyn Layer,
+    file: &LayerFile,
+    trace: &mut Trace,
+) -> Result<Vec<u8>, PeelError> {
+    let buffer = layer
+        .codec()
+        .decode(file.payload)
+        .map_err(|source| PeelError::Encoding {
+            layer: Some(file.number),
+            source,
+        })?;
+    trace.stage("payload", &buffer);
+
+    let output = layer
+        .decode_traced(buffer, trace)
+        .map_err(|e| e.at_layer(file.number))?;
+    trace.stage("output", &output);
+    Ok(output)
+}
+
+/// Solve a layer file, dumping its stages to a `layer-NN` directory of
+/// `dir`, even when the transform fails.
+pub fn solve_layer_dumped(
+    layer: &dyn Layer,
+    file: &LayerFile,
+    dir: impl AsRef<Path>,
+) -> Result<Vec<u8>, PeelError> {
+    let mut trace = Trace::recording();
+    let result = solve_layer_traced(layer, file, &mut trace);
+    dump_layer(dir, file.number, &trace)?;
+    result
+}
+
+fn dump_layer(dir: impl AsRef<Path>, n: u8, trace: &Trace) -> Result<(), PeelError> {
+    let dir = dir.as_ref().join(format!("layer-{:02}", n));
+    write_dump(dir, trace).map_err(|source| PeelError::Io {
+        layer: Some(n),
+        source,
+    })
+}
+
+/// Check that the output of a layer is the text of the next one, or only
+/// text for the last layer, whose output is the core.
+pub fn check_output(file: &LayerFile, b: Vec<u8>) -> Result<String, PeelError> {
+    let error = |source| PeelError::Output {
+        layer: file.number,
+        source,
+    };
+
+    let s = String::from_utf8(b).map_err(|e| error(LayerFileError::NotText(e.utf8_error())))?;
+    if file.number < file.total {
+        check_layer(&s, file.number + 1).map_err(error)?;
+    }
+    Ok(s)
+}
+
+/// Peel every layer from the text of the first one, saving the following
+/// ones to `dir` as named by `naming`, and return the core, the output of
+/// the last layer.
+///
+/// Each output is checked before being saved. A first text without a layer
+/// header is already the core. With a `dump` directory, the stages of every
+/// layer are dumped to it. With a `report`, every layer peeled is added to
+/// it.
+pub fn peel(
+    registry: &Registry,
+    first: &str,
+    dir: impl AsRef<Path>,
+    naming: &LayerNaming,
+    dump: Option<&Path>,
+    mut report: Option<&mut PeelReport>,
+) -> Result<String, PeelError> {
+    let mut s = first.to_string();
+
+    loop {
+        let file = match LayerFile::parse(&s) {
+            Ok(file) => file,
+            Err(LayerFileError::MissingHeader) => return Ok(s),
+            Err(e) => return Err(e.into()),
+        };
+        let layer = resolve_layer(registry, &file)?;
+        let next = file.number + 1;
+
+        let mut trace = match dump {
+            Some(_) => Trace::recording(),
+            None => Trace::new(),
+        };
+        let start = Instant::now();
+        let result = solve_layer_traced(layer, &file, &mut trace);
+        let duration = start.elapsed();
+        if let Some(dump) = dump {
+            dump_layer(dump, file.number, &trace)?;
+        }
+
+        let output = check_output(&file, result?)?;
+        if let Some(report) = report.as_deref_mut() {
+            let layer = LayerReport::new(layer, &file, &trace, output.as_bytes(), duration);
+            report.layers.push(layer);
+        }
+        save_layer(naming.path(&dir, next), output.as_bytes()).map_err(|source| PeelError::Io {
+            layer: Some(next),
+            source,
+        })?;
+        if file.number >= file.total {
+            return Ok(output);
+        }
+        s = output;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer0::AsciiLayer;
+    use crate::layer3::XorLayer;
+    use crate::layer6::VmLayer;
+
+    const LAYER: &str = include_str!("../layers/00.txt");
+
+    #[test]
+    fn test_solve_layer() {
+        let file = LayerFile::parse(LAYER).unwrap();
+        let next = String::from_utf8(solve_layer(&AsciiLayer, &file).unwrap()).unwrap();
+        let next = LayerFile::parse(&next).unwrap();
+
+        assert_eq!(next.number, 1);
+        assert_eq!(next.title, "Bitwise Operations");
+    }
+
+    #[test]
+    fn test_resolve_layer() {
+        let registry = Registry::standard();
+        let s = LAYER.replace("Layer 0/6: ASCII85", "Layer 0/6: Unknown");
+        let file = LayerFile::parse(&s).unwrap();
+        assert_eq!(resolve_layer(&registry, &file).unwrap().number(), 0);
+
+        let s = LAYER.replace("Layer 0/6: ASCII85", "Layer 9/9: Unknown");
+        let file = LayerFile::parse(&s).unwrap();
+        assert!(matches!(
+            resolve_layer(&registry, &file),
+            Err(PeelError::UnknownLayer { layer: 9, .. })
+        ));
+    }
+
+    #[test]
+    fn test_solve_layer_traced() {
+        let file = LayerFile::parse(LAYER).unwrap();
+        let mut trace = Trace::recording();
+        let output = solve_layer_traced(&AsciiLayer, &file, &mut trace).unwrap();
+
+        let names = trace.stages().iter().map(|s| s.name.as_str());
+        assert_eq!(names.collect::<Vec<_>>(), ["payload", "output"]);
+        assert_eq!(trace.stages()[1].data, output);
+    }
+
+    #[test]
+    fn test_solve_renumbered_layer() {
+        // solvers are found by title, whatever the number of the file
+        let file =
+            LayerFile::parse("==[ Layer 2/6: Virtual Machine ]==\n==[ Payload ]==\n<~z~>").unwrap();
+        let e = solve_layer(&VmLayer, &file).unwrap_err();
+        assert!(matches!(e, PeelError::Vm { layer: 2, .. }));
+
+        let file = LayerFile::parse("==[ Layer 4/6: ASCII85 ]==\n==[ Payload ]==\n<~v~>").unwrap();
+        let e = solve_layer(&AsciiLayer, &file).unwrap_err();
+        assert!(matches!(e, PeelError::Encoding { layer: Some(4), .. }));
+    }
+
+    #[test]
+    fn test_check_layer() {
+        assert_eq!(check_layer(LAYER, 0).unwrap().number, 0);
+        assert_eq!(
+            check_layer(LAYER, 1),
+            Err(LayerFileError::UnexpectedNumber {
+                expected: 1,
+                found: 0
+            })
+        );
+        assert_eq!(check_layer("", 0), Err(LayerFileError::MissingHeader));
+    }
+
+    #[test]
+    fn test_check_output() {
+        let file = LayerFile::parse(LAYER).unwrap();
+        let next = LAYER.replace("Layer 0/6", "Layer 1/6");
+
+        assert_eq!(
+            check_output(&file, next.clone().into_bytes()).unwrap(),
+            next
+        );
+        assert!(matches!(
+            check_output(&file, LAYER.into()),
+            Err(PeelError::Output {
+                layer: 0,
+                source: LayerFileError::UnexpectedNumber {
+                    expected: 1,
+                    found: 0
+                }
+            })
+        ));
+        assert!(matches!(
+            check_output(&file, b"core".to_vec()),
+            Err(PeelError::Output {
+                layer: 0,
+                source: LayerFileError::MissingHeader
+            })
+        ));
+        assert!(matches!(
+            check_output(&file, vec![0xff, 0xfe]),
+            Err(PeelError::Output {
+                layer: 0,
+                source: LayerFileError::NotText(_)
+            })
+        ));
+
+        // the last layer outputs the core
+        let last = LAYER.replace("Layer 0/6", "Layer 6/6");
+        let file = LayerFile::parse(&last).unwrap();
+        assert_eq!(check_output(&file, b"core".to_vec()).unwrap(), "core");
+    }
+
+    #[test]
+    fn test_find_resume_layer() {
+        let dir = std::env::temp_dir().join(format!("data-onion-resume-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let naming = LayerNaming::default();
+        let layer = |n: u8| LAYER.replace("Layer 0/6", &format!("Layer {}/6", n));
+
+        let mut found = vec![find_resume_layer(&dir, &naming).unwrap()];
+        for n in 0..3 {
+            fs::write(naming.path(&dir, n), layer(n)).unwrap();
+        }
+        found.push(find_resume_layer(&dir, &naming).unwrap());
+        // a gap stops the chain
+        fs::write(naming.path(&dir, 4), layer(4)).unwrap();
+        found.push(find_resume_layer(&dir, &naming).unwrap());
+        // so does a file with the header of another layer
+        fs::write(naming.path(&dir, 1), layer(2)).unwrap();
+        found.push(find_resume_layer(&dir, &naming).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(found, [None, Some(2), Some(2), Some(0)]);
+    }
+
+    #[test]
+    fn test_peel_last_number() {
+        let s = LAYER.replace("Layer 0/6", "Layer 255/255");
+        let dir = std::env::temp_dir();
+
+        assert!(matches!(
+            peel(
+                &Registry::standard(),
+                &s,
+                dir,
+                &LayerNaming::default(),
+                None,
+                None
+            ),
+            Err(PeelError::Payload {
+                source: LayerFileError::InvalidHeader(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_errors() {
+        let solve = |layer: &dyn Layer, s: &str| solve_layer(layer, &LayerFile::parse(s).unwrap());
+
+        assert!(matches!(
+            solve(&AsciiLayer, &LAYER.replace("~>", "")),
+            Err(PeelError::Encoding { layer: Some(0), .. })
+        ));
+        assert!(matches!(
+            solve(
+                &XorLayer,
+                "==[ Layer 3/6: XOR ]===\n==[ Payload ]=====\n<~z~>"
+            ),
+            Err(PeelError::XorKey { layer: 3, .. })
+        ));
+    }
+}
@@ -0,0 +1,84 @@
+//! Machine-readable report of a peel.
+
+use crate::dump::{Stat, Trace};
+use crate::layer::Layer;
+use crate::layer_file::LayerFile;
+use data_encoding::HEXLOWER;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LayerReport {
+    pub number: u8,
+    /// Title found in the header of the layer file.
+    pub title: String,
+    /// Name of the codec decoding the payload.
+    pub decoder: String,
+    /// Name of the layer solving the file.
+    pub solver: String,
+    /// Size of the encoded payload, in bytes.
+    pub input_size: usize,
+    pub output_size: usize,
+    /// SHA-256 of the output, in lowercase hex.
+    pub sha256: String,
+    /// Time spent decoding the payload and running the transform.
+    pub duration_ms: f64,
+    /// Statistics recorded by the layer, such as its key or rejected data.
+    pub stats: BTreeMap<String, Stat>,
+}
+
+impl LayerReport {
+    pub fn new(
+        layer: &dyn Layer,
+        file: &LayerFile,
+        trace: &Trace,
+        output: &[u8],
+        duration: Duration,
+    ) -> Self {
+        Self {
+            number: file.number,
+            title: file.title.to_string(),
+            decoder: layer.codec().name().to_string(),
+            solver: layer.name().to_string(),
+            input_size: file.payload.len(),
+            output_size: output.len(),
+            sha256: HEXLOWER.encode(&Sha256::digest(output)),
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            stats: trace.stats().clone(),
+        }
+    }
+}
+
+/// Layers peeled in order, and the error stopping the peel, if any.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PeelReport {
+    pub layers: Vec<LayerReport>,
+    pub error: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer0::AsciiLayer;
+
+    #[test]
+    fn test_layer_report() {
+        let file = LayerFile::parse("==[ Layer 0/6: ASCII85 ]==\n==[ Payload ]==\n<~z~>").unwrap();
+        let mut trace = Trace::new();
+        trace.stat("key", "00ff");
+
+        let report = LayerReport::new(&AsciiLayer, &file, &trace, b"abc", Duration::from_millis(3));
+        assert_eq!(report.input_size, 6);
+        assert_eq!(report.output_size, 3);
+        assert_eq!(
+            report.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(report.duration_ms, 3.0);
+        assert_eq!(report.decoder, "ascii85");
+        assert_eq!(report.solver, "ASCII85");
+        assert_eq!(report.stats["key"], Stat::from("00ff"));
+    }
+}